@@ -1,8 +1,43 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rox::{build_file, BuildOptions, CompileOptions, OptimizationLevel};
+use std::path::PathBuf;
+use std::process::Command;
+use tempfile::TempDir;
 
-fn benchmark(c: &mut Criterion) {
-    c.bench_function("", |_| {});
+/// Compares the runtime of the same program built at each optimization level
+fn optimization_levels(c: &mut Criterion) {
+    let program =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches/fibonacci.rox");
+    let output_directory = TempDir::new().unwrap();
+    let mut group = c.benchmark_group("fibonacci");
+    group.sample_size(10);
+
+    for level in OptimizationLevel::ALL.iter() {
+        let executable = output_directory.path().join(level.to_string());
+        let options = CompileOptions::new(*level);
+        build_file(
+            program.clone(),
+            executable.clone(),
+            &options,
+            &BuildOptions::default(),
+        )
+        .unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(level),
+            &executable,
+            |b, executable| {
+                b.iter(|| {
+                    assert!(Command::new(executable)
+                        .status()
+                        .unwrap()
+                        .success())
+                })
+            },
+        );
+    }
+    group.finish();
 }
 
-criterion_group!(benches, benchmark);
+criterion_group!(benches, optimization_levels);
 criterion_main!(benches);
@@ -4,12 +4,32 @@
 //! This module is the executable module for the Rox roxc.
 extern crate rox;
 
-use rox::{build_file, run_file};
+use rox::{
+    build_file, check_file, run_file, BuildOptions, ColorMode, CompileOptions,
+    DiagnosticOptions, EmitKind, ErrorFormat, OptimizationLevel,
+    RelocationModel, RunOptions, TargetOptions,
+};
 use std::{path::PathBuf, process::exit};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 #[structopt(about = "The command line interface to the Rox compiler.")]
+struct Cli {
+    /// Prints the exit code once the command finishes
+    #[structopt(short, long, global = true)]
+    verbose: bool,
+    /// How to print errors: human, or json for one JSON object per line
+    #[structopt(long, global = true, default_value = "human")]
+    error_format: ErrorFormat,
+    /// When to color errors: auto, always or never.
+    /// `auto` colors errors on a terminal unless `NO_COLOR` is set.
+    #[structopt(long, global = true, default_value = "auto")]
+    color: ColorMode,
+    #[structopt(subcommand)]
+    command: Roxc,
+}
+
+#[derive(Debug, StructOpt)]
 enum Roxc {
     /// Compiles and links the program
     Build {
@@ -19,23 +39,152 @@ enum Roxc {
         /// The name of the output executable
         #[structopt(short, long)]
         output: PathBuf,
+        /// Lets integer arithmetic wrap on overflow instead of panicking.
+        /// Overflow is only checked at -O0 and -O1 by default.
+        #[structopt(long)]
+        no_overflow_checks: bool,
+        /// The optimization level: 0, 1, 2, 3 or s to optimize for size
+        #[structopt(short = "O", default_value = "0")]
+        optimization_level: OptimizationLevel,
+        #[structopt(flatten)]
+        target: TargetFlags,
+        /// The files to write: any of ast, typed-ast, llvm-ir, llvm-bc, asm, obj and exe.
+        /// Files other than the executable are written next to the output path.
+        #[structopt(long, use_delimiter = true, default_value = "exe")]
+        emit: Vec<EmitKind>,
+        /// The linker to use, such as `arm-linux-gnueabihf-gcc` when cross-compiling.
+        /// Defaults to `cc`.
+        #[structopt(long, parse(from_os_str))]
+        linker: Option<PathBuf>,
+        /// The sysroot to pass to the linker
+        #[structopt(long, parse(from_os_str))]
+        sysroot: Option<PathBuf>,
+    },
+    /// Parses and type checks the program without compiling it
+    Check {
+        /// The file to check
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
     },
     /// Executes the program with Rox's JIT compiler
     Run {
         /// The file to run
         #[structopt(parse(from_os_str))]
         file: PathBuf,
+        /// Runs the program with LLVM's `lli` instead of the built-in JIT
+        #[structopt(long)]
+        lli: bool,
+        /// Lets integer arithmetic wrap on overflow instead of panicking.
+        /// Overflow is only checked at -O0 and -O1 by default.
+        #[structopt(long)]
+        no_overflow_checks: bool,
+        /// The optimization level: 0, 1, 2, 3 or s to optimize for size
+        #[structopt(short = "O", default_value = "0")]
+        optimization_level: OptimizationLevel,
+        /// Arguments passed to the program, after `--`
+        #[structopt(last = true)]
+        args: Vec<String>,
     },
 }
 
+/// Options for the machine that `rox build` compiles for
+#[derive(Debug, StructOpt)]
+struct TargetFlags {
+    /// The target triple to compile for, such as `x86_64-unknown-linux-gnu`.
+    /// Defaults to the host.
+    #[structopt(long)]
+    target: Option<String>,
+    /// The CPU to compile for, or `native` for this machine's CPU
+    #[structopt(long)]
+    target_cpu: Option<String>,
+    /// CPU features to enable or disable, such as `+avx2,-sse4.1`
+    #[structopt(long)]
+    target_features: Option<String>,
+    /// The relocation model: pic, static, dynamic-no-pic or default
+    #[structopt(long, default_value = "pic")]
+    relocation_model: RelocationModel,
+}
+
+impl From<TargetFlags> for TargetOptions {
+    fn from(flags: TargetFlags) -> Self {
+        TargetOptions {
+            triple: flags.target,
+            cpu: flags.target_cpu,
+            features: flags.target_features,
+            relocation_model: flags.relocation_model,
+        }
+    }
+}
+
+fn compile_options(
+    no_overflow_checks: bool,
+    optimization_level: OptimizationLevel,
+    target: TargetOptions,
+) -> CompileOptions {
+    CompileOptions {
+        overflow_checks: !no_overflow_checks
+            && optimization_level.checks_overflow_by_default(),
+        optimization_level,
+        target,
+    }
+}
+
 /// # Rox
 /// This is the executable for running the Rox roxc.
 fn main() {
-    let args = Roxc::from_args();
-    let exit_status = match args {
-        Roxc::Build { file, output, .. } => build_file(file, output),
-        Roxc::Run { file } => run_file(file),
+    let cli = Cli::from_args();
+    let result = match cli.command {
+        Roxc::Build {
+            file,
+            output,
+            no_overflow_checks,
+            optimization_level,
+            target,
+            emit,
+            linker,
+            sysroot,
+        } => build_file(
+            file,
+            output,
+            &compile_options(
+                no_overflow_checks,
+                optimization_level,
+                target.into(),
+            ),
+            &BuildOptions {
+                emit,
+                linker,
+                sysroot,
+            },
+        )
+        .map(|()| 0),
+        Roxc::Check { file } => check_file(file).map(|()| 0),
+        Roxc::Run {
+            file,
+            lli,
+            no_overflow_checks,
+            optimization_level,
+            args,
+        } => run_file(
+            file,
+            &compile_options(
+                no_overflow_checks,
+                optimization_level,
+                TargetOptions::default(),
+            ),
+            &RunOptions { use_lli: lli, args },
+        ),
     };
-    println!("rox: {}", exit_status);
-    exit(exit_status.code().unwrap_or(0));
+    let exit_code = result.unwrap_or_else(|error| {
+        // There's nowhere left to report a failure to write to stderr
+        let _ = error.emit(&DiagnosticOptions {
+            format: cli.error_format,
+            color: cli.color,
+        });
+        error.exit_code()
+    });
+    if cli.verbose {
+        eprintln!("rox: exit code: {}", exit_code);
+    }
+    exit(exit_code);
 }
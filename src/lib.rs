@@ -14,81 +14,562 @@ extern crate lalrpop_util;
 
 mod roxc;
 
-pub use crate::roxc::Result;
-use crate::roxc::{get_builtin_types, parse_file, Compiler, Stack};
+use crate::roxc::{
+    catch_panics, check_program, enter_phase, get_builtin_types, parse_string,
+    write_assembly_file, write_object_file, Compiler, Phase, SourceLocations,
+    Stack,
+};
+pub use crate::roxc::{
+    ColorMode, CompileOptions, DiagnosticOptions, Error, ErrorFormat,
+    OptimizationLevel, RelocationModel, RoxError, TargetOptions,
+};
 use inkwell::context::Context;
+use inkwell::module::Module;
 use inkwell::passes::PassManager;
-use std::{collections::HashMap, process::Command};
-use std::{path::PathBuf, process::ExitStatus};
+use inkwell::targets::{InitializationConfig, Target, TargetMachine};
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::ffi::CString;
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::fs::{read_to_string, write};
+#[cfg(unix)]
+use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::str::FromStr;
 use tempfile::NamedTempFile;
 
-/// `build_file` reads the contents at `path` and outputs
-/// an executable at the given `output` path
-pub fn build_file(path: PathBuf, output: PathBuf) -> ExitStatus {
+/// The result of building, checking or running a Rox program
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Options for [`build_file`] that don't change the compiled program
+#[derive(Clone, Debug)]
+pub struct BuildOptions {
+    /// The files to write. Everything but the executable is written
+    /// next to the output path, with the extension of its kind.
+    pub emit: Vec<EmitKind>,
+    /// The program used to link the executable, which is `cc` by default.
+    /// Cross-compiled programs need a linker for the target, such as
+    /// `arm-linux-gnueabihf-gcc`.
+    pub linker: Option<PathBuf>,
+    /// The directory that the linker finds the target's libraries in
+    pub sysroot: Option<PathBuf>,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            emit: vec![EmitKind::Exe],
+            linker: None,
+            sysroot: None,
+        }
+    }
+}
+
+/// The kinds of files that `rox build --emit` can write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmitKind {
+    /// The parsed syntax tree, written to `.ast`
+    Ast,
+    /// The syntax tree with types from type checking, written to `.typed-ast`
+    TypedAst,
+    /// Textual LLVM IR, written to `.ll`
+    LlvmIr,
+    /// LLVM bitcode, written to `.bc`
+    LlvmBc,
+    /// Assembly for the target, written to `.s`
+    Asm,
+    /// An object file for the target, written to `.o`
+    Obj,
+    /// The linked executable, written to the output path
+    Exe,
+}
+
+impl EmitKind {
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            EmitKind::Ast => Some("ast"),
+            EmitKind::TypedAst => Some("typed-ast"),
+            EmitKind::LlvmIr => Some("ll"),
+            EmitKind::LlvmBc => Some("bc"),
+            EmitKind::Asm => Some("s"),
+            EmitKind::Obj => Some("o"),
+            EmitKind::Exe => None,
+        }
+    }
+}
+
+impl FromStr for EmitKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> std::result::Result<Self, Self::Err> {
+        match kind {
+            "ast" => Ok(EmitKind::Ast),
+            "typed-ast" => Ok(EmitKind::TypedAst),
+            "llvm-ir" => Ok(EmitKind::LlvmIr),
+            "llvm-bc" => Ok(EmitKind::LlvmBc),
+            "asm" => Ok(EmitKind::Asm),
+            "obj" => Ok(EmitKind::Obj),
+            "exe" => Ok(EmitKind::Exe),
+            _ => Err(format!(
+                "Unknown output kind `{}`, expected one of ast, typed-ast, llvm-ir, llvm-bc, asm, obj or exe",
+                kind
+            )),
+        }
+    }
+}
+
+/// The files requested with `--emit`, which are written next to the output
+struct Artifacts<'a> {
+    output: &'a Path,
+    kinds: &'a [EmitKind],
+}
+
+impl Artifacts<'_> {
+    /// The path to write a kind of file to, or `None` if it wasn't requested
+    fn path(&self, kind: EmitKind) -> Option<PathBuf> {
+        if !self.kinds.contains(&kind) {
+            return None;
+        }
+        Some(match kind.extension() {
+            Some(extension) => self.output.with_extension(extension),
+            None => self.output.to_path_buf(),
+        })
+    }
+
+    /// Pretty prints a syntax tree, if it was requested
+    fn write_tree(&self, kind: EmitKind, tree: &impl Debug) -> Result<()> {
+        match self.path(kind) {
+            Some(path) => write(&path, format!("{:#?}\n", tree))
+                .map_err(|error| write_error(&path, &error.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_error(path: &Path, reason: &str) -> Error {
+    Error::Io(
+        RoxError::with_file_placeholder(&format!(
+            "Failed to write `{}`",
+            path.display()
+        ))
+        .with_note(reason),
+    )
+}
+
+/// Options for [`run_file`] that don't change the compiled program
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    /// Runs the program with LLVM's `lli` instead of compiling it with the
+    /// built-in JIT, which needs an LLVM toolchain on `PATH`
+    pub use_lli: bool,
+    /// The command line arguments passed to the program, not
+    /// including the program's name
+    pub args: Vec<String>,
+}
+
+/// Exit code of a process that was stopped without an exit code
+const FAILURE_EXIT_CODE: i32 = 1;
+
+/// `build_file` reads the contents at `path` and writes the files
+/// requested in the build options, which is an executable at the given
+/// `output` path by default.
+///
+/// # Errors
+/// Returns an [`Error`] if the program doesn't compile, a file can't be
+/// read or written, or the linker fails.
+pub fn build_file(
+    path: PathBuf,
+    output: PathBuf,
+    options: &CompileOptions,
+    build_options: &BuildOptions,
+) -> Result<()> {
     let context = Context::create();
+    let target_machine = create_target_machine(options)?;
+    let artifacts = Artifacts {
+        output: &output,
+        kinds: &build_options.emit,
+    };
+    let module =
+        compile_file(path, &context, &target_machine, options, &artifacts)?;
+    write_artifacts(&module, &target_machine, &artifacts, build_options)
+}
+
+fn create_target_machine(options: &CompileOptions) -> Result<TargetMachine> {
+    options
+        .target
+        .create_target_machine(options.optimization_level)
+        .map_err(Error::Codegen)
+}
+
+fn write_artifacts(
+    module: &Module,
+    target_machine: &TargetMachine,
+    artifacts: &Artifacts,
+    build_options: &BuildOptions,
+) -> Result<()> {
+    if let Some(path) = artifacts.path(EmitKind::LlvmIr) {
+        module
+            .print_to_file(&path)
+            .map_err(|error| write_error(&path, &error.to_string()))?;
+    }
+    if let Some(path) = artifacts.path(EmitKind::LlvmBc) {
+        if !module.write_bitcode_to_path(&path) {
+            return Err(write_error(&path, "LLVM could not write the bitcode"));
+        }
+    }
+    if let Some(path) = artifacts.path(EmitKind::Asm) {
+        write_assembly_file(target_machine, module, &path)
+            .map_err(Error::Codegen)?;
+    }
+    let executable_path = match artifacts.path(EmitKind::Exe) {
+        Some(path) => path,
+        None => {
+            if let Some(path) = artifacts.path(EmitKind::Obj) {
+                write_object_file(target_machine, module, &path)
+                    .map_err(Error::Codegen)?;
+            }
+            return Ok(());
+        }
+    };
 
     // N.B. NamedTempFile instances won't be cleaned up if the destructor isn't run,
     // but we need a file path, so `tempfile()` won't work here
-    let temp_bitcode_file = NamedTempFile::new().unwrap();
-    let temp_object_file = NamedTempFile::new().unwrap();
-    let bitcode_file_path = temp_bitcode_file.into_temp_path();
-    let object_file_path = temp_object_file.into_temp_path();
-
-    compile_file(path, bitcode_file_path.as_os_str().into(), &context);
-
-    Command::new("llc")
-        .args(&[
-            bitcode_file_path.as_os_str().to_str().unwrap(),
-            "-filetype=obj",
-            "-o",
-            object_file_path.as_os_str().to_str().unwrap(),
-        ])
-        .output()
-        .expect("Failed to execute `llc`");
-    Command::new("cc")
-        .args(&[
-            object_file_path.to_str().unwrap(),
-            "-o",
-            output.to_str().unwrap(),
-        ])
-        .output()
-        .expect("Failed to link with `cc`")
-        .status
-}
-
-/// `run_file` reads the contents of the given path
-/// and runs them through the roxc.
+    let temp_object_file_path;
+    let object_file_path = match artifacts.path(EmitKind::Obj) {
+        Some(path) => path,
+        None => {
+            temp_object_file_path = NamedTempFile::new()
+                .map_err(|error| {
+                    Error::Io(
+                        RoxError::with_file_placeholder(
+                            "Failed to create a temporary object file",
+                        )
+                        .with_note(&error.to_string()),
+                    )
+                })?
+                .into_temp_path();
+            temp_object_file_path.to_path_buf()
+        }
+    };
+    write_object_file(target_machine, module, &object_file_path)
+        .map_err(Error::Codegen)?;
+
+    let linker = build_options
+        .linker
+        .as_deref()
+        .unwrap_or_else(|| Path::new("cc"));
+    let mut command = Command::new(linker);
+    command
+        .arg(&object_file_path)
+        .arg("-o")
+        .arg(executable_path);
+    if let Some(sysroot) = &build_options.sysroot {
+        let mut sysroot_flag = OsString::from("--sysroot=");
+        sysroot_flag.push(sysroot);
+        command.arg(sysroot_flag);
+    }
+    let status = command.status().map_err(|error| {
+        Error::Toolchain(
+            RoxError::with_file_placeholder(&format!(
+                "Failed to run the linker `{}`",
+                linker.display()
+            ))
+            .with_note(&error.to_string()),
+        )
+    })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Toolchain(RoxError::with_file_placeholder(&format!(
+            "The linker `{}` exited with code {}",
+            linker.display(),
+            exit_code(status)
+        ))))
+    }
+}
+
+/// `check_file` parses and type checks the file at `path` without
+/// generating any code.
 ///
 /// # Errors
-/// Currently, the Rox roxc exits with a
-/// `1` error code if it fails to compile. In the future,
-/// it would likely be a good idea to refactor these
-/// to have POSIX compliant error codes, or at least
-/// some consistent error code system.
-pub fn run_file(path: PathBuf) -> ExitStatus {
+/// Returns every parse error, or every type error if the program parses.
+pub fn check_file(path: PathBuf) -> Result<()> {
+    let source = read_source(&path)?;
+    catch_panics(|| {
+        enter_phase(Phase::Parsing);
+        let declarations =
+            parse_string(&source, &path).map_err(Error::Parse)?;
+        enter_phase(Phase::TypeChecking);
+        check_program(declarations).map(|_| ()).map_err(Error::Type)
+    })
+    .map_err(|error| error.in_file(&path, &source))
+}
+
+fn read_source(path: &Path) -> Result<String> {
+    read_to_string(path).map_err(|error| {
+        Error::Io(
+            RoxError::with_file_placeholder(&format!(
+                "Failed to read `{}`",
+                path.display()
+            ))
+            .with_note(&error.to_string()),
+        )
+    })
+}
+
+/// `run_file` reads the contents of the given path, compiles them
+/// and runs the program's `main` function, returning its exit code.
+///
+/// By default, the program is compiled in-process with LLVM's JIT, and
+/// its `main` runs in a forked child process, so that a runtime panic or
+/// a call to `exit` only ends the program. `extern` functions, such as
+/// `puts`, are resolved against the symbols of the current process.
+/// Set [`RunOptions::use_lli`] to run the program with `lli` instead,
+/// which is also used on platforms without `fork`.
+///
+/// Only the calling thread is copied into the child process, so a lock
+/// held by another thread of the caller at the time of the fork stays
+/// locked in the child. The child only runs the program, which uses
+/// `libc` but none of the caller's code, but callers with other threads
+/// that hold `libc` locks, such as one blocked writing to a stream,
+/// should set [`RunOptions::use_lli`].
+///
+/// # Errors
+/// Returns an [`Error`] if the program doesn't compile or can't be
+/// started. A program that runs but fails, such as by panicking,
+/// returns its non-zero exit code instead. The CLI exits with
+/// [`Error::exit_code`] for each kind of error.
+pub fn run_file(
+    path: PathBuf,
+    options: &CompileOptions,
+    run_options: &RunOptions,
+) -> Result<i32> {
     let context = Context::create();
-    let temp_bitcode_file = NamedTempFile::new().unwrap();
-    let file_path = temp_bitcode_file.into_temp_path();
-    compile_file(path, file_path.as_os_str().into(), &context);
-    Command::new("lli")
-        .args(&[file_path.as_os_str().to_str().unwrap()])
-        .output()
-        .expect("Failed to execute `lli`")
-        .status
-}
-
-fn compile_file<T>(input_file: T, bitcode_file_output: T, context: &Context)
-where
-    T: Into<PathBuf> + Sized + Clone,
-{
-    let module = context.create_module("rox");
-    let declarations = match parse_file(input_file) {
-        Ok(decl) => decl,
-        Err(error) => {
-            error.emit_error().unwrap();
-            return;
-        }
+    let target_machine = create_target_machine(options)?;
+    let artifacts = Artifacts {
+        output: Path::new(""),
+        kinds: &[],
     };
+    let module = compile_file(
+        path.clone(),
+        &context,
+        &target_machine,
+        options,
+        &artifacts,
+    )?;
+    if run_options.use_lli {
+        run_with_lli(&module, options, &run_options.args)
+    } else {
+        run_with_jit(&module, options, &path, &run_options.args)
+    }
+}
+
+/// The C `main` generated for a Rox program
+#[cfg(unix)]
+type Main = unsafe extern "C" fn(c_int, *const *const c_char) -> c_int;
+
+/// Compiles the program with LLVM's JIT and calls its `main` function in a
+/// child process, as if it were the `main` of a C program with the given
+/// arguments. The program shares this process's stdin, stdout and stderr.
+///
+/// The program's panics and calls to `exit` end the child process, so
+/// they can't take down this one, and its exit code is returned.
+#[cfg(unix)]
+fn run_with_jit(
+    module: &Module,
+    options: &CompileOptions,
+    path: &Path,
+    args: &[String],
+) -> Result<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    Target::initialize_native(&InitializationConfig::default()).map_err(
+        |error| Error::Codegen(RoxError::with_file_placeholder(&error)),
+    )?;
+    let engine = module
+        .create_jit_execution_engine(options.optimization_level.llvm_level())
+        .map_err(|error| {
+            Error::Codegen(RoxError::with_file_placeholder(
+                &error.to_string_lossy(),
+            ))
+        })?;
+    // The program is compiled before forking, so that the child process
+    // only runs the program's code
+    let address = engine.get_function_address("main").map_err(|error| {
+        Error::Codegen(RoxError::with_file_placeholder(&format!(
+            "Failed to compile `main`: {:?}",
+            error
+        )))
+    })?;
+    let main = unsafe { std::mem::transmute::<usize, Main>(address) };
+    let program_name = path.display().to_string();
+    let argv = std::iter::once(program_name.as_str())
+        .chain(args.iter().map(String::as_str))
+        .map(|argument| {
+            CString::new(argument).map_err(|_| {
+                Error::Io(RoxError::with_file_placeholder(&format!(
+                    "The program argument `{}` contains a NUL byte",
+                    argument.escape_debug()
+                )))
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let argv_pointers = argv
+        .iter()
+        .map(|argument| argument.as_ptr())
+        .chain(std::iter::once(std::ptr::null()))
+        .collect::<Vec<_>>();
+
+    // Anything buffered by this process's `libc` would be written
+    // twice if it were copied into the child
+    unsafe {
+        libc::fflush(std::ptr::null_mut());
+    }
+    let child = unsafe { libc::fork() };
+    if child == 0 {
+        unsafe {
+            let status = main(argv.len() as c_int, argv_pointers.as_ptr());
+            // The program's output is buffered by the child's `libc`,
+            // which `_exit` doesn't flush
+            libc::fflush(std::ptr::null_mut());
+            libc::_exit(status)
+        }
+    }
+    if child < 0 {
+        return Err(start_error(std::io::Error::last_os_error()));
+    }
+    let mut status = 0;
+    while unsafe { libc::waitpid(child, &mut status, 0) } < 0 {
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(start_error(error));
+        }
+    }
+    Ok(exit_code(ExitStatus::from_raw(status)))
+}
+
+#[cfg(unix)]
+fn start_error(error: std::io::Error) -> Error {
+    Error::Io(
+        RoxError::with_file_placeholder("Failed to start the program")
+            .with_note(&error.to_string()),
+    )
+}
+
+/// Without `fork`, there's no way to keep the program's panics from
+/// ending this process, so it's run with `lli` instead
+#[cfg(not(unix))]
+fn run_with_jit(
+    module: &Module,
+    options: &CompileOptions,
+    _path: &Path,
+    args: &[String],
+) -> Result<i32> {
+    run_with_lli(module, options, args)
+}
+
+fn run_with_lli(
+    module: &Module,
+    options: &CompileOptions,
+    args: &[String],
+) -> Result<i32> {
+    let file_path = NamedTempFile::new()
+        .map_err(|error| {
+            Error::Io(
+                RoxError::with_file_placeholder(
+                    "Failed to create a temporary bitcode file",
+                )
+                .with_note(&error.to_string()),
+            )
+        })?
+        .into_temp_path();
+    if !module.write_bitcode_to_path(&file_path) {
+        return Err(write_error(
+            &file_path,
+            "LLVM could not write the bitcode",
+        ));
+    }
+    // The program inherits `rox`'s stdio, so its output is streamed
+    // straight to the terminal
+    let status = Command::new("lli")
+        .arg(codegen_optimization_flag(options))
+        .arg(&file_path)
+        .args(args)
+        .status()
+        .map_err(|_| {
+            Error::Toolchain(
+                RoxError::with_file_placeholder("Failed to execute `lli`")
+                    .with_note("`lli` is part of the LLVM toolchain, and needs to be on your `PATH`"),
+            )
+        })?;
+    Ok(exit_code(status))
+}
+
+/// The exit code of a child process. A process killed by a signal
+/// gets the shell's exit code of `128` plus the signal number.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(FAILURE_EXIT_CODE)
+}
+
+/// The `-O` flag for `lli`, which doesn't have a size level
+fn codegen_optimization_flag(options: &CompileOptions) -> String {
+    format!("-O{}", options.optimization_level.llvm_level() as u32)
+}
+
+/// Compiles and optimizes the file at `path` for the target machine.
+/// The syntax trees are written along the way, if they were requested.
+/// Code is only generated for programs without parse or type errors.
+///
+/// A panic in the compiler is reported as an internal compiler error
+/// that points at the code being compiled.
+fn compile_file<'ctx>(
+    path: PathBuf,
+    context: &'ctx Context,
+    target_machine: &TargetMachine,
+    options: &CompileOptions,
+    artifacts: &Artifacts,
+) -> Result<Module<'ctx>> {
+    let source = read_source(&path)?;
+    catch_panics(|| {
+        compile_source(
+            &path,
+            &source,
+            context,
+            target_machine,
+            options,
+            artifacts,
+        )
+    })
+    .map_err(|error| error.in_file(&path, &source))
+}
+
+fn compile_source<'ctx>(
+    path: &Path,
+    source: &str,
+    context: &'ctx Context,
+    target_machine: &TargetMachine,
+    options: &CompileOptions,
+    artifacts: &Artifacts,
+) -> Result<Module<'ctx>> {
+    let module = context.create_module("rox");
+    let target_data = target_machine.get_target_data();
+    module.set_triple(&target_machine.get_triple());
+    module.set_data_layout(&target_data.get_data_layout());
+    let locations = SourceLocations::new(path, source);
+    enter_phase(Phase::Parsing);
+    let declarations = parse_string(source, path).map_err(Error::Parse)?;
+    artifacts.write_tree(EmitKind::Ast, &declarations)?;
+    enter_phase(Phase::TypeChecking);
+    let tagged_statements = check_program(declarations).map_err(Error::Type)?;
+    artifacts.write_tree(EmitKind::TypedAst, &tagged_statements)?;
 
     // TODO: Clean this shit up
     let mut environment_stack = Stack::new();
@@ -99,10 +580,18 @@ where
     let mut compiler = Compiler::new(
         context,
         &module,
+        &target_data,
         &function_pass_manager,
         &mut environment_stack,
         &mut function_stack,
+        options,
+        &locations,
     );
-    let is_successful = compiler.compile(declarations).is_ok();
-    compiler.finish(bitcode_file_output, is_successful);
+    enter_phase(Phase::CodeGeneration);
+    compiler
+        .compile(&tagged_statements)
+        .map_err(Error::Codegen)?;
+    enter_phase(Phase::Optimization);
+    compiler.finish();
+    Ok(module)
 }
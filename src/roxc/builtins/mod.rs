@@ -1,6 +1,6 @@
 use crate::roxc::parser::FunctionDeclaration;
 use crate::roxc::stack::Stack;
-use crate::roxc::{Type, TypeConstructor, TypeEnv, VariableEnv};
+use crate::roxc::{Binding, Type, TypeConstructor, TypeEnv, VariableEnv};
 use std::collections::HashMap;
 
 pub(crate) fn get_builtin_types() -> (
@@ -9,16 +9,56 @@ pub(crate) fn get_builtin_types() -> (
     Stack<HashMap<String, FunctionDeclaration>>,
 ) {
     let mut type_env = HashMap::new();
-    let variable_env = HashMap::new();
+    let mut variable_env = HashMap::new();
 
     type_env.insert(
         "Int".to_string(),
         Type::Apply(TypeConstructor::Int, Vec::new()),
     );
+    type_env.insert(
+        "Int32".to_string(),
+        Type::Apply(TypeConstructor::Int, Vec::new()),
+    );
+    type_env.insert(
+        "Int8".to_string(),
+        Type::Apply(TypeConstructor::Int8, Vec::new()),
+    );
+    type_env.insert(
+        "Int16".to_string(),
+        Type::Apply(TypeConstructor::Int16, Vec::new()),
+    );
+    type_env.insert(
+        "Int64".to_string(),
+        Type::Apply(TypeConstructor::Int64, Vec::new()),
+    );
+    type_env.insert(
+        "UInt8".to_string(),
+        Type::Apply(TypeConstructor::UInt8, Vec::new()),
+    );
+    type_env.insert(
+        "UInt16".to_string(),
+        Type::Apply(TypeConstructor::UInt16, Vec::new()),
+    );
+    type_env.insert(
+        "UInt32".to_string(),
+        Type::Apply(TypeConstructor::UInt32, Vec::new()),
+    );
+    type_env.insert(
+        "UInt64".to_string(),
+        Type::Apply(TypeConstructor::UInt64, Vec::new()),
+    );
     type_env.insert(
         "Float".to_string(),
         Type::Apply(TypeConstructor::Float, Vec::new()),
     );
+    type_env.insert(
+        "Float32".to_string(),
+        Type::Apply(TypeConstructor::Float32, Vec::new()),
+    );
+    type_env.insert(
+        "Char".to_string(),
+        Type::Apply(TypeConstructor::Char, Vec::new()),
+    );
     type_env.insert(
         "String".to_string(),
         Type::Apply(TypeConstructor::String, Vec::new()),
@@ -31,8 +71,59 @@ pub(crate) fn get_builtin_types() -> (
         "Bool".to_string(),
         Type::Apply(TypeConstructor::Bool, Vec::new()),
     );
+
+    // Builtin functions are implemented by the `runtime` module
+    variable_env.insert(
+        "len".to_string(),
+        Binding::item(builtin_function(
+            vec![TypeConstructor::String],
+            TypeConstructor::Int,
+        )),
+    );
+    variable_env.insert(
+        "substring".to_string(),
+        Binding::item(builtin_function(
+            vec![
+                TypeConstructor::String,
+                TypeConstructor::Int,
+                TypeConstructor::Int,
+            ],
+            TypeConstructor::String,
+        )),
+    );
+    // `env(name)` is the value of an environment variable, or `""` if it isn't set
+    variable_env.insert(
+        "env".to_string(),
+        Binding::item(builtin_function(
+            vec![TypeConstructor::String],
+            TypeConstructor::String,
+        )),
+    );
+    variable_env.insert(
+        "exit".to_string(),
+        Binding::item(builtin_function(
+            vec![TypeConstructor::Int],
+            TypeConstructor::Void,
+        )),
+    );
+
     let mut function_stack: Stack<HashMap<String, FunctionDeclaration>> =
         Stack::new();
     function_stack.push(HashMap::new());
     (type_env, variable_env, function_stack)
 }
+
+fn builtin_function(
+    parameters: Vec<TypeConstructor>,
+    return_type: TypeConstructor,
+) -> Type {
+    let mut types = parameters
+        .into_iter()
+        .map(|constructor| Type::Apply(constructor, Vec::new()))
+        .collect::<Vec<_>>();
+    types.push(Type::Apply(return_type, Vec::new()));
+    Type::PolymorphicType(
+        Vec::new(),
+        Box::new(Type::Apply(TypeConstructor::Arrow, types)),
+    )
+}
@@ -1,86 +1,229 @@
 use crate::roxc::compiler_state::CompilerState;
+use crate::roxc::runtime;
 use crate::roxc::{
-    analyse_program, FunctionDeclaration, FunctionTranslator, Identifier,
-    Result, RoxError, Stack, Statement, TaggedStatement, Type,
+    FunctionDeclaration, FunctionTranslator, Identifier, Mutability, Result,
+    RoxError, SourceLocations, Stack, TaggedExpression, TaggedStatement,
+    TargetOptions, Type,
 };
 use inkwell::builder::Builder;
 use inkwell::context::Context;
 use inkwell::module::Module;
-use inkwell::passes::PassManager;
+use inkwell::passes::{PassManager, PassManagerBuilder};
+use inkwell::targets::TargetData;
 use inkwell::types::{BasicType, BasicTypeEnum};
-use inkwell::values::{BasicValue, FunctionValue, PointerValue};
+use inkwell::values::{
+    BasicValue, BasicValueEnum, FunctionValue, PointerValue,
+};
+use inkwell::AddressSpace;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Options that change the code generated for a Rox program
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    /// Whether integer arithmetic panics on overflow and division by zero
+    /// instead of silently wrapping. This is on by default for `-O0` and
+    /// `-O1`, and off for the higher optimization levels.
+    pub overflow_checks: bool,
+    /// How much LLVM optimizes the program. Programs aren't optimized by default.
+    pub optimization_level: OptimizationLevel,
+    /// The machine that `rox build` generates code for
+    pub target: TargetOptions,
+}
+
+impl CompileOptions {
+    /// The default options for an optimization level
+    pub fn new(optimization_level: OptimizationLevel) -> Self {
+        CompileOptions {
+            overflow_checks: optimization_level.checks_overflow_by_default(),
+            optimization_level,
+            target: TargetOptions::default(),
+        }
+    }
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions::new(OptimizationLevel::O0)
+    }
+}
+
+/// The optimization levels accepted by `-O`, which match those of `clang`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptimizationLevel {
+    /// No optimizations, for the fastest compile times
+    O0,
+    /// Cheap optimizations, such as promoting local variables to registers
+    O1,
+    /// Most optimizations, including inlining
+    O2,
+    /// All of the optimizations from `O2`, plus more aggressive inlining
+    /// and vectorization
+    O3,
+    /// The optimizations from `O2` that don't increase code size
+    Os,
+}
+
+impl OptimizationLevel {
+    /// Every optimization level accepted by `-O`
+    pub const ALL: [OptimizationLevel; 5] = [
+        OptimizationLevel::O0,
+        OptimizationLevel::O1,
+        OptimizationLevel::O2,
+        OptimizationLevel::O3,
+        OptimizationLevel::Os,
+    ];
+
+    /// Whether integer overflow is checked at this level, unless it's
+    /// turned off with `--no-overflow-checks`. As with debug and release
+    /// builds in Rust, only the unoptimized levels check for overflow.
+    pub fn checks_overflow_by_default(self) -> bool {
+        matches!(self, OptimizationLevel::O0 | OptimizationLevel::O1)
+    }
+
+    /// The level of LLVM's code generator, which has no separate size level
+    pub(crate) fn llvm_level(self) -> inkwell::OptimizationLevel {
+        match self {
+            OptimizationLevel::O0 => inkwell::OptimizationLevel::None,
+            OptimizationLevel::O1 => inkwell::OptimizationLevel::Less,
+            OptimizationLevel::O2 | OptimizationLevel::Os => {
+                inkwell::OptimizationLevel::Default
+            }
+            OptimizationLevel::O3 => inkwell::OptimizationLevel::Aggressive,
+        }
+    }
+
+    /// Configures the same function and module pass pipelines
+    /// that `clang` uses at this level
+    fn pass_manager_builder(self) -> PassManagerBuilder {
+        let builder = PassManagerBuilder::create();
+        builder.set_optimization_level(self.llvm_level());
+        // These are `clang`'s inlining thresholds for each level
+        match self {
+            OptimizationLevel::O0 => {}
+            OptimizationLevel::O1 | OptimizationLevel::O2 => {
+                builder.set_inliner_with_threshold(225)
+            }
+            OptimizationLevel::O3 => builder.set_inliner_with_threshold(275),
+            OptimizationLevel::Os => {
+                builder.set_size_level(1);
+                builder.set_inliner_with_threshold(75);
+            }
+        }
+        builder
+    }
+}
+
+impl FromStr for OptimizationLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> std::result::Result<Self, Self::Err> {
+        match level {
+            "0" => Ok(OptimizationLevel::O0),
+            "1" => Ok(OptimizationLevel::O1),
+            "2" => Ok(OptimizationLevel::O2),
+            "3" => Ok(OptimizationLevel::O3),
+            "s" => Ok(OptimizationLevel::Os),
+            _ => Err(format!(
+                "Unknown optimization level `{}`, expected one of 0, 1, 2, 3 or s",
+                level
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for OptimizationLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self {
+            OptimizationLevel::O0 => "0",
+            OptimizationLevel::O1 => "1",
+            OptimizationLevel::O2 => "2",
+            OptimizationLevel::O3 => "3",
+            OptimizationLevel::Os => "s",
+        };
+        write!(f, "-O{}", level)
+    }
+}
 
 pub struct Compiler<'module, 'ctx, 'm> {
     context: &'ctx Context,
     pub(crate) module: &'m Module<'ctx>,
+    /// The layout of types on the machine the program is compiled for
+    target_data: &'module TargetData,
     function_pass_manager: &'module PassManager<FunctionValue<'ctx>>,
     environment_stack:
         &'module mut Stack<HashMap<Identifier, PointerValue<'ctx>>>,
     function_stack:
         &'module mut Stack<HashMap<Identifier, FunctionDeclaration>>,
+    options: &'module CompileOptions,
+    /// Used to report the location of runtime panics
+    locations: &'module SourceLocations,
 }
 
 impl<'a, 'ctx, 'm> Compiler<'a, 'ctx, 'm> {
     pub fn new(
         context: &'ctx Context,
         module: &'m Module<'ctx>,
+        target_data: &'a TargetData,
         function_pass_manager: &'a PassManager<FunctionValue<'ctx>>,
         environment_stack: &'a mut Stack<
             HashMap<Identifier, PointerValue<'ctx>>,
         >,
         function_stack: &'a mut Stack<HashMap<Identifier, FunctionDeclaration>>,
+        options: &'a CompileOptions,
+        locations: &'a SourceLocations,
     ) -> Self {
-        // TODO: Which of these do we actually want?
-        // Only runs with --release
-        // function_pass_manager.add_instruction_combining_pass();
-        // function_pass_manager.add_reassociate_pass();
-        // function_pass_manager.add_gvn_pass();
-        // function_pass_manager.add_cfg_simplification_pass();
-        // function_pass_manager.add_basic_alias_analysis_pass();
-        // function_pass_manager.add_promote_memory_to_register_pass();
-        // function_pass_manager.add_instruction_combining_pass();
-
+        options
+            .optimization_level
+            .pass_manager_builder()
+            .populate_function_pass_manager(function_pass_manager);
         function_pass_manager.initialize();
 
         Compiler {
             context,
             module,
+            target_data,
             function_pass_manager,
             environment_stack,
             function_stack,
+            options,
+            locations,
         }
     }
 
-    pub fn compile(&mut self, declarations: Vec<Box<Statement>>) -> Result<()> {
-        match self.compile_statements(&declarations) {
-            Err(e) => Err(e),
-            Ok(_) => Ok(()),
-        }
-    }
-
-    pub fn finish(
-        &self,
-        path: impl Into<PathBuf> + Sized,
-        print_stderr: bool,
-    ) -> bool {
-        if print_stderr {
-            self.module.print_to_stderr();
-        }
-
-        self.module.write_bitcode_to_path(&path.into())
+    /// Generates code for a type checked program
+    pub(crate) fn compile(
+        &mut self,
+        tagged_statements: &[TaggedStatement],
+    ) -> Result<()> {
+        runtime::add_runtime_functions(
+            self.context,
+            self.module,
+            self.target_data,
+        );
+        // Statics are visible to every function, wherever they're declared
+        let (statics, declarations): (Vec<_>, Vec<_>) =
+            tagged_statements.iter().partition(|statement| {
+                matches!(statement, TaggedStatement::StaticDeclaration(..))
+            });
+        statics.into_iter().chain(declarations).try_for_each(
+            |declaration| self.translate_declaration(declaration),
+        )?;
+        runtime::add_entry_point(self.context, self.module, self.target_data);
+        Ok(())
     }
 
-    fn compile_statements(
-        &mut self,
-        declarations: &[Box<Statement>],
-    ) -> Result<Vec<()>> {
-        let tagged_statements = analyse_program(declarations.to_vec())?;
-        tagged_statements
-            .iter()
-            .map(|declaration| self.translate_declaration(declaration))
-            .collect()
+    /// Runs the module-level optimization passes once every
+    /// function has been compiled
+    pub fn finish(&self) {
+        // Function passes run as each function is compiled, but passes
+        // like inlining need to see the whole module
+        let module_pass_manager = PassManager::create(());
+        self.options
+            .optimization_level
+            .pass_manager_builder()
+            .populate_module_pass_manager(&module_pass_manager);
+        module_pass_manager.run_on(self.module);
     }
 
     fn translate_declaration(
@@ -95,7 +238,8 @@ impl<'a, 'ctx, 'm> Compiler<'a, 'ctx, 'm> {
                     function_declaration.name.clone(),
                     &function_declaration.params,
                     &function_declaration.return_type,
-                );
+                    true,
+                )?;
                 Ok(())
             }
             TaggedStatement::FunctionDeclaration(func_declaration, block) => {
@@ -105,13 +249,17 @@ impl<'a, 'ctx, 'm> Compiler<'a, 'ctx, 'm> {
                     return_type,
                 } = func_declaration;
                 let mut fn_value = self.compile_prototype(
-                    func_name.clone(),
+                    runtime::function_symbol(func_name).to_string(),
                     params,
                     return_type,
-                );
+                    false,
+                )?;
                 let entry = self.context.append_basic_block(fn_value, "entry");
                 let builder = self.context.create_builder();
                 builder.position_at_end(entry);
+                // Each function gets its own scope on top of the globals
+                let globals = self.environment_stack.top().clone();
+                self.environment_stack.push(globals);
                 self.environment_stack.top_mut().reserve(params.len());
 
                 fn_value.get_param_iter().enumerate().for_each(
@@ -139,6 +287,9 @@ impl<'a, 'ctx, 'm> Compiler<'a, 'ctx, 'm> {
                     self.context,
                     fn_value,
                     self.module,
+                    self.target_data,
+                    self.options.overflow_checks,
+                    self.locations,
                 );
 
                 let mut function_translator = FunctionTranslator::new(
@@ -147,7 +298,9 @@ impl<'a, 'ctx, 'm> Compiler<'a, 'ctx, 'm> {
                     self.function_stack.top_mut(),
                 );
 
-                function_translator.translate_function(block);
+                let translated = function_translator.translate_function(block);
+                self.environment_stack.pop();
+                translated?;
 
                 if fn_value.verify(true) {
                     self.function_pass_manager.run_on(&fn_value);
@@ -162,7 +315,29 @@ impl<'a, 'ctx, 'm> Compiler<'a, 'ctx, 'm> {
             // Struct _declarations_ are entirely for the type system,
             // and we'll later use the field order of the type
             // to instantiate the struct.
-            TaggedStatement::StructDeclaration => Ok(()),
+            TaggedStatement::StructDeclaration
+            | TaggedStatement::ConstDeclaration => Ok(()),
+            TaggedStatement::StaticDeclaration(
+                name,
+                mutability,
+                type_,
+                value,
+            ) => {
+                let llvm_type = CompilerState::get_type(
+                    self.context,
+                    self.target_data,
+                    type_,
+                    self.environment_stack.top(),
+                )?
+                .expect("Statics cannot be Void");
+                let global = self.module.add_global(llvm_type, None, name);
+                global.set_initializer(&self.constant_value(value)?);
+                global.set_constant(*mutability == Mutability::Immutable);
+                self.environment_stack
+                    .top_mut()
+                    .insert(name.clone(), global.as_pointer_value());
+                Ok(())
+            }
             TaggedStatement::Expression(_)
             | TaggedStatement::IfElse(_, _, _)
             | TaggedStatement::Return(_) => {
@@ -173,36 +348,118 @@ impl<'a, 'ctx, 'm> Compiler<'a, 'ctx, 'm> {
         }
     }
 
+    /// Builds the LLVM constant for the literal value of a static
+    fn constant_value(
+        &self,
+        value: &TaggedExpression,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let llvm_type = |type_: &Type| -> Result<BasicTypeEnum<'ctx>> {
+            Ok(CompilerState::get_type(
+                self.context,
+                self.target_data,
+                type_,
+                self.environment_stack.top(),
+            )?
+            .expect("Constants cannot be Void"))
+        };
+        match value {
+            TaggedExpression::Boolean(boolean) => Ok(self
+                .context
+                .bool_type()
+                .const_int(*boolean as u64, false)
+                .into()),
+            TaggedExpression::Char(character) => Ok(self
+                .context
+                .i32_type()
+                .const_int(character.value as u64, false)
+                .into()),
+            TaggedExpression::Int(int, type_) => Ok(llvm_type(type_)?
+                .into_int_type()
+                .const_int(int.value, false)
+                .into()),
+            TaggedExpression::Float(float, type_) => Ok(llvm_type(type_)?
+                .into_float_type()
+                .const_float(float.value)
+                .into()),
+            TaggedExpression::String(string) => {
+                let i8_type = self.context.i8_type();
+                let bytes = string
+                    .value
+                    .bytes()
+                    .chain(std::iter::once(0))
+                    .map(|byte| i8_type.const_int(byte as u64, false))
+                    .collect::<Vec<_>>();
+                let characters = self.module.add_global(
+                    i8_type.array_type(bytes.len() as u32),
+                    None,
+                    "str",
+                );
+                characters.set_initializer(&i8_type.const_array(&bytes));
+                characters.set_constant(true);
+                let pointer = characters
+                    .as_pointer_value()
+                    .const_cast(i8_type.ptr_type(AddressSpace::Generic));
+                let length = self
+                    .context
+                    .i32_type()
+                    .const_int(string.value.len() as u64, false);
+                Ok(self
+                    .context
+                    .const_struct(&[pointer.into(), length.into()], false)
+                    .into())
+            }
+            TaggedExpression::Tuple(elements, _) => {
+                let element_values = elements
+                    .iter()
+                    .map(|element| self.constant_value(element))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(self.context.const_struct(&element_values, false).into())
+            }
+            _ => Err(RoxError::with_file_placeholder(
+                "This kind of value is not yet supported in statics",
+            )),
+        }
+    }
+
     /// Compile the function signature
+    ///
+    /// `extern` functions use the C representation of their
+    /// parameter and return types (see `CompilerState::get_extern_type`).
     fn compile_prototype(
         &self,
         func_name: String,
         params: &[(Identifier, Type)],
         return_type: &Type,
-    ) -> FunctionValue<'ctx> {
+        is_extern: bool,
+    ) -> Result<FunctionValue<'ctx>> {
+        let get_type = if is_extern {
+            CompilerState::get_extern_type
+        } else {
+            CompilerState::get_type
+        };
         let param_types = params
             .iter()
-            .map(|(_ident, ty)| {
-                CompilerState::get_type(
+            .map(|(ident, ty)| {
+                get_type(
                     self.context,
+                    self.target_data,
                     ty,
                     self.environment_stack.top(),
-                    Some(0),
-                )
-                .unwrap_or_else(|| {
-                    panic!(
-                    "Cannot handle void parameter type or undefined type {:?}",
-                    ty
-                )
+                )?
+                .ok_or_else(|| {
+                    RoxError::with_file_placeholder(&format!(
+                        "The parameter `{}` of `{}` cannot have type Void",
+                        ident, func_name
+                    ))
                 })
             })
-            .collect::<Vec<_>>();
-        let fn_type = match CompilerState::get_type(
+            .collect::<Result<Vec<_>>>()?;
+        let fn_type = match get_type(
             self.context,
+            self.target_data,
             return_type,
             self.environment_stack.top(),
-            None,
-        ) {
+        )? {
             Some(t) => t.fn_type(
                 param_types
                     .iter()
@@ -224,11 +481,9 @@ impl<'a, 'ctx, 'm> Compiler<'a, 'ctx, 'm> {
             self.module.add_function(func_name.as_str(), fn_type, None);
         fn_value
             .get_param_iter()
-            .enumerate()
-            .for_each(|(index, arg)| {
-                arg.set_name(params.get(index).unwrap().0.as_str())
-            });
-        fn_value
+            .zip(params)
+            .for_each(|(arg, (name, _))| arg.set_name(name));
+        Ok(fn_value)
     }
 }
 
@@ -1,21 +1,29 @@
-use crate::roxc::{Identifier, Operation, Type};
+use crate::roxc::runtime;
+use crate::roxc::{
+    not_yet_supported, Identifier, Operation, Result, SourceLocations, Span,
+    Spanned, Type, TypeConstructor, Unary,
+};
 use inkwell::context::Context;
 use inkwell::module::Module;
-use inkwell::types::{BasicType, BasicTypeEnum};
+use inkwell::targets::TargetData;
+use inkwell::types::{BasicType, BasicTypeEnum, FloatType, IntType};
 use inkwell::values::{
-    BasicValue, BasicValueEnum, FloatValue, FunctionValue, InstructionValue,
-    PointerValue,
+    AggregateValueEnum, BasicValue, BasicValueEnum, FloatValue, FunctionValue,
+    InstructionValue, PointerValue, StructValue,
 };
 use inkwell::{basic_block::BasicBlock, values::IntValue};
 use inkwell::{builder::Builder, IntPredicate};
 use inkwell::{AddressSpace, FloatPredicate};
-use std::{collections::HashMap, convert::TryInto};
+use std::collections::HashMap;
 
 pub struct CompilerState<'f, 'c> {
     builder: Builder<'c>,
     context: &'c Context,
     function: FunctionValue<'c>,
     module: &'f Module<'c>,
+    target_data: &'f TargetData,
+    overflow_checks: bool,
+    locations: &'f SourceLocations,
 }
 
 impl<'f, 'c> CompilerState<'f, 'c> {
@@ -24,12 +32,18 @@ impl<'f, 'c> CompilerState<'f, 'c> {
         context: &'c Context,
         function: FunctionValue<'c>,
         module: &'f Module<'c>,
+        target_data: &'f TargetData,
+        overflow_checks: bool,
+        locations: &'f SourceLocations,
     ) -> Self {
         Self {
             builder,
             context,
             function,
             module,
+            target_data,
+            overflow_checks,
+            locations,
         }
     }
 
@@ -37,73 +51,145 @@ impl<'f, 'c> CompilerState<'f, 'c> {
         self.context
     }
 
+    pub fn get_target_data(&self) -> &'f TargetData {
+        self.target_data
+    }
+
     pub fn get_function(&self, name: &str) -> Option<FunctionValue<'c>> {
-        self.module.get_function(name)
+        self.module.get_function(runtime::function_symbol(name))
     }
 
     pub fn get_type(
         context: &'c Context,
+        target_data: &TargetData,
         ty: &Type,
         environment: &'f HashMap<Identifier, PointerValue<'c>>,
-        maybe_len: Option<usize>,
-    ) -> Option<BasicTypeEnum<'c>> {
+    ) -> Result<Option<BasicTypeEnum<'c>>> {
         match ty {
             Type::Apply(constructor, _type_arguments) => {
                 use super::semant::TypeConstructor::*;
                 match constructor {
-                    Bool => Some(context.bool_type().into()),
-                    Float => Some(context.f64_type().into()),
-                    Int => Some(context.i32_type().into()),
-                    String => Some(
-                        // TODO: Should strings be built on top of the actual array implementation (below)?
-                        context
-                            .i8_type()
-                            .array_type((maybe_len.unwrap() + 1) as u32)
-                            .ptr_type(AddressSpace::Generic)
-                            .into(),
-                    ),
-                    Void => None,
+                    Bool => Ok(Some(context.bool_type().into())),
+                    Char => Ok(Some(context.i32_type().into())),
+                    Float => Ok(Some(context.f64_type().into())),
+                    Float32 => Ok(Some(context.f32_type().into())),
+                    Int8 | UInt8 => Ok(Some(context.i8_type().into())),
+                    Int16 | UInt16 => Ok(Some(context.i16_type().into())),
+                    Int | UInt32 => Ok(Some(context.i32_type().into())),
+                    Int64 | UInt64 => Ok(Some(context.i64_type().into())),
+                    String => Ok(Some(runtime::string_type(context).into())),
+                    Void => Ok(None),
                     Array(inner_type) => {
                         let inner_type = CompilerState::get_type(
                             context,
+                            target_data,
                             inner_type,
                             environment,
-                            maybe_len,
-                        )
-                        .unwrap();
+                        )?
+                        .expect("Array elements cannot be Void");
                         let array_type = inner_type
                             .array_type(0)
                             .ptr_type(AddressSpace::Generic);
-                        // TODO: This just store the array length as an i64, but should probably check if that's a reasonable assumption
-                        // or if LLVM supports unsigned int types
-                        //
                         // N.B. Struct types are represented as a struct where the first
                         // field is the inner type of the array, and the second is the length
-                        // of the array.
+                        // of the array, which is a pointer-sized integer for the target.
                         // We'll need to do manual bound checks on these to prevent out-of-bounds access.
                         let struct_type = context.struct_type(
                             &[
                                 array_type.as_basic_type_enum(),
-                                context.i32_type().as_basic_type_enum(),
+                                context
+                                    .ptr_sized_int_type(target_data, None)
+                                    .as_basic_type_enum(),
                             ],
                             false,
                         ); // Not packing these structs
-                        Some(struct_type.as_basic_type_enum())
+                        Ok(Some(struct_type.as_basic_type_enum()))
                     }
-                    Arrow | Record(_) | FunctionType(_, _) | Unique(_) => {
-                        todo!()
+                    Record(fields) => {
+                        let field_types = fields
+                            .iter()
+                            .map(|(_name, field_type)| {
+                                Ok(CompilerState::get_type(
+                                    context,
+                                    target_data,
+                                    field_type,
+                                    environment,
+                                )?
+                                .expect("Struct fields cannot be Void"))
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        Ok(Some(
+                            context
+                                .struct_type(field_types.as_slice(), false)
+                                .as_basic_type_enum(),
+                        ))
                     }
+                    // Tuples are anonymous structs of their elements
+                    Tuple(elements) => {
+                        let element_types = elements
+                            .iter()
+                            .map(|element_type| {
+                                Ok(CompilerState::get_type(
+                                    context,
+                                    target_data,
+                                    element_type,
+                                    environment,
+                                )?
+                                .expect("Tuple elements cannot be Void"))
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        Ok(Some(
+                            context
+                                .struct_type(element_types.as_slice(), false)
+                                .as_basic_type_enum(),
+                        ))
+                    }
+                    Arrow | FunctionType(_, _) => {
+                        Err(not_yet_supported("Using a function as a value"))
+                    }
+                    Unique(_) => Err(not_yet_supported("Unique types")),
                 }
             }
-            Type::Variable(variable_name) => environment
+            Type::Variable(variable_name) => Ok(environment
                 .get(variable_name)
-                .map(|var| var.get_type().as_basic_type_enum()),
+                .map(|var| var.get_type().as_basic_type_enum())),
+            // Non-generic structs are still wrapped in a polymorphic type
+            Type::PolymorphicType(formal_arguments, inner_type)
+                if formal_arguments.is_empty() =>
+            {
+                CompilerState::get_type(
+                    context,
+                    target_data,
+                    inner_type,
+                    environment,
+                )
+            }
             Type::PolymorphicType(_formal_arguments, _types) => {
-                unimplemented!()
+                Err(not_yet_supported("Compiling generic types"))
             }
         }
     }
 
+    /// Gets the type used for a value passed to or returned from
+    /// an `extern` function. This is the same as `get_type`, except
+    /// that strings are passed as NUL-terminated C strings.
+    pub fn get_extern_type(
+        context: &'c Context,
+        target_data: &TargetData,
+        ty: &Type,
+        environment: &'f HashMap<Identifier, PointerValue<'c>>,
+    ) -> Result<Option<BasicTypeEnum<'c>>> {
+        match ty {
+            Type::Apply(TypeConstructor::String, _) => Ok(Some(
+                context
+                    .i8_type()
+                    .ptr_type(AddressSpace::Generic)
+                    .as_basic_type_enum(),
+            )),
+            _ => CompilerState::get_type(context, target_data, ty, environment),
+        }
+    }
+
     pub fn build_return(
         &self,
         return_: Option<&dyn BasicValue<'c>>,
@@ -117,20 +203,12 @@ impl<'f, 'c> CompilerState<'f, 'c> {
 
     pub fn build_conditional(
         &self,
-        conditional_expression_value: FloatValue,
-        branch_name: &str,
+        conditional_expression_value: IntValue,
         if_block: BasicBlock,
         else_block: BasicBlock,
     ) {
-        let zero_const = self.context.f64_type().const_zero();
-        let conditional = self.builder.build_float_compare(
-            FloatPredicate::ONE,
-            conditional_expression_value,
-            zero_const,
-            branch_name,
-        );
         self.builder.build_conditional_branch(
-            conditional,
+            conditional_expression_value,
             if_block,
             else_block,
         );
@@ -142,21 +220,25 @@ impl<'f, 'c> CompilerState<'f, 'c> {
 
     pub unsafe fn build_array_access(
         &self,
-        array_struct: PointerValue<'c>,
+        array_struct: StructValue<'c>,
         index: IntValue<'c>,
     ) -> PointerValue<'c> {
         // Load array length and do a bounds check
-        let _array_len =
-            self.builder.build_struct_gep(array_struct, 1, "").unwrap();
+        let _array_len = self
+            .builder
+            .build_extract_value(array_struct, 1, "")
+            .unwrap();
         // TODO: bounds check here
 
         // index from struct
-        let one = self.context.i64_type().const_int(1, false);
-        let array_ref =
-            dbg!(self.builder.build_struct_gep(array_struct, 0, "").unwrap());
-        let loaded_array = self.build_load(array_ref).into_pointer_value();
+        let zero = self.context.i32_type().const_zero();
+        let array_pointer = self
+            .builder
+            .build_extract_value(array_struct, 0, "")
+            .unwrap()
+            .into_pointer_value();
         self.builder
-            .build_in_bounds_gep(loaded_array, &[one, index], "")
+            .build_in_bounds_gep(array_pointer, &[zero, index], "")
     }
 
     pub fn build_load(&self, pointer: PointerValue<'c>) -> BasicValueEnum<'c> {
@@ -178,28 +260,103 @@ impl<'f, 'c> CompilerState<'f, 'c> {
         items: &[BasicValueEnum<'c>],
         type_: BasicTypeEnum<'c>,
     ) -> PointerValue<'c> {
-        let len = self.context.i32_type().const_int(items.len() as u64, false);
-        let array_pointer_type = type_
-            .into_struct_type()
-            .get_field_type_at_index(0)
-            .expect("Array type did not have inner array type at index 0")
-            .into_pointer_type()
-            .get_element_type()
-            .into_array_type();
-        let allocation =
-            self.builder.build_array_alloca(array_pointer_type, len, "");
-        let one = self.context.i64_type().const_int(1, false);
+        let (array_pointer, len) =
+            self.build_array_allocation(items.len(), type_);
+        let zero = self.context.i32_type().const_zero();
 
         items.iter().enumerate().for_each(|(index, item)| {
-            let index = self.context.i16_type().const_int(index as u64, false);
+            let index = self.context.i32_type().const_int(index as u64, false);
             let pointer = unsafe {
-                self.builder
-                    .build_in_bounds_gep(allocation, &[one, index], "")
+                self.builder.build_in_bounds_gep(
+                    array_pointer,
+                    &[zero, index],
+                    "",
+                )
             };
             self.build_store(pointer, *item);
         });
 
-        self.build_array_struct(allocation, len)
+        self.build_array_struct(array_pointer, len)
+    }
+
+    /// Allocates an array of `length` copies of `value`, and returns
+    /// a pointer to the array. The elements are stored by a loop, so
+    /// that the size of the code doesn't grow with the length.
+    pub fn build_repeated_array(
+        &self,
+        value: BasicValueEnum<'c>,
+        length: usize,
+        type_: BasicTypeEnum<'c>,
+    ) -> PointerValue<'c> {
+        let (array_pointer, len) = self.build_array_allocation(length, type_);
+        if length > 0 {
+            let index_type = len.get_type();
+            let zero = index_type.const_zero();
+            let entry_block = self.builder.get_insert_block().unwrap();
+            let loop_block = self.append_basic_block("repeat");
+            let done_block = self.append_basic_block("repeated");
+            self.builder.build_unconditional_branch(loop_block);
+
+            self.builder.position_at_end(loop_block);
+            let index = self.builder.build_phi(index_type, "index");
+            let index_value = index.as_basic_value().into_int_value();
+            let pointer = unsafe {
+                self.builder.build_in_bounds_gep(
+                    array_pointer,
+                    &[zero, index_value],
+                    "",
+                )
+            };
+            self.build_store(pointer, value);
+            let next = self.builder.build_int_add(
+                index_value,
+                index_type.const_int(1, false),
+                "next",
+            );
+            index.add_incoming(&[(&zero, entry_block), (&next, loop_block)]);
+            let has_next = self.builder.build_int_compare(
+                IntPredicate::ULT,
+                next,
+                len,
+                "has_next",
+            );
+            self.builder
+                .build_conditional_branch(has_next, loop_block, done_block);
+            self.builder.position_at_end(done_block);
+        }
+
+        self.build_array_struct(array_pointer, len)
+    }
+
+    /// Allocates the elements of an array with the given length, returning
+    /// the pointer to them and the length as the array's length type
+    fn build_array_allocation(
+        &self,
+        length: usize,
+        type_: BasicTypeEnum<'c>,
+    ) -> (PointerValue<'c>, IntValue<'c>) {
+        let len = type_
+            .into_struct_type()
+            .get_field_type_at_index(1)
+            .expect("Array type did not have a length at index 1")
+            .into_int_type()
+            .const_int(length as u64, false);
+        let array_pointer_type = type_
+            .into_struct_type()
+            .get_field_type_at_index(0)
+            .expect("Array type did not have inner array type at index 0")
+            .into_pointer_type();
+        let element_type =
+            array_pointer_type.get_element_type().into_array_type();
+        let allocation = self.builder.build_array_alloca(
+            element_type.get_element_type(),
+            len,
+            "",
+        );
+        let array_pointer =
+            self.builder
+                .build_pointer_cast(allocation, array_pointer_type, "");
+        (array_pointer, len)
     }
 
     pub fn bool_literal(&self, boolean: bool) -> BasicValueEnum<'c> {
@@ -209,19 +366,87 @@ impl<'f, 'c> CompilerState<'f, 'c> {
             .into()
     }
 
-    pub fn int_literal(&self, num: i32) -> BasicValueEnum<'c> {
+    pub fn char_literal(&self, character: char) -> BasicValueEnum<'c> {
         self.context
             .i32_type()
-            .const_int(num.try_into().unwrap(), false)
+            .const_int(character as u64, false)
             .into()
     }
 
-    pub fn float_literal(&self, num: f64) -> BasicValueEnum<'c> {
-        self.context.f64_type().const_float(num).into()
+    /// N.B. The type checker has already checked that `num` fits in `int_type`
+    pub fn int_literal(
+        &self,
+        num: u64,
+        int_type: IntType<'c>,
+    ) -> BasicValueEnum<'c> {
+        int_type.const_int(num, false).into()
+    }
+
+    pub fn float_literal(
+        &self,
+        num: f64,
+        float_type: FloatType<'c>,
+    ) -> BasicValueEnum<'c> {
+        float_type.const_float(num).into()
     }
 
     pub fn string_literal(&self, string: &str) -> BasicValueEnum<'c> {
-        self.context.const_string(string.as_bytes(), false).into()
+        let pointer = self.builder.build_global_string_ptr(string, "str");
+        let len = self
+            .context
+            .i32_type()
+            .const_int(string.len() as u64, false);
+        let with_pointer = self
+            .builder
+            .build_insert_value(
+                runtime::string_type(self.context).get_undef(),
+                pointer.as_pointer_value(),
+                0,
+                "",
+            )
+            .unwrap();
+        self.builder
+            .build_insert_value(with_pointer, len, 1, "")
+            .unwrap()
+            .into_struct_value()
+            .into()
+    }
+
+    pub fn build_struct(
+        &self,
+        type_: BasicTypeEnum<'c>,
+        fields: &[BasicValueEnum<'c>],
+    ) -> BasicValueEnum<'c> {
+        let struct_type = type_.into_struct_type();
+        let struct_value = fields.iter().enumerate().fold(
+            struct_type.get_undef().into(),
+            |struct_value: AggregateValueEnum<'c>, (index, field)| {
+                self.builder
+                    .build_insert_value(struct_value, *field, index as u32, "")
+                    .unwrap()
+            },
+        );
+        struct_value.into_struct_value().into()
+    }
+
+    pub fn build_field_pointer(
+        &self,
+        struct_pointer: PointerValue<'c>,
+        index: u32,
+    ) -> PointerValue<'c> {
+        self.builder
+            .build_struct_gep(struct_pointer, index, "")
+            .expect("Struct field index out of range")
+    }
+
+    pub fn build_field_access(
+        &self,
+        struct_value: StructValue<'c>,
+        index: u32,
+    ) -> BasicValueEnum<'c> {
+        self.builder
+            .build_extract_value(struct_value, index, "")
+            .expect("Struct field index out of range")
     }
 
     pub fn store_variable(
@@ -266,82 +491,571 @@ impl<'f, 'c> CompilerState<'f, 'c> {
         struct_allocation
     }
 
+    /// `is_signed` picks between the signed and unsigned versions of
+    /// division, comparison and right shifts, since LLVM integers
+    /// don't carry a sign
     pub fn build_int_operation(
         &self,
         lval: IntValue<'c>,
         rval: IntValue<'c>,
-        operation: &Operation,
+        operation: &Spanned<Operation>,
+        is_signed: bool,
     ) -> BasicValueEnum<'c> {
+        if self.overflow_checks {
+            if let Some(result) = self
+                .build_checked_int_operation(lval, rval, operation, is_signed)
+            {
+                return result;
+            }
+        }
         use Operation::*;
-        match operation {
-            Add => self.builder.build_int_add(lval, rval, "tmpadd").into(),
-            Subtract => self.builder.build_int_sub(lval, rval, "tmpsub").into(),
-            Multiply => self.builder.build_int_mul(lval, rval, "tmpmul").into(),
-            Divide => self
-                .builder
-                .build_int_signed_div(lval, rval, "tmpdiv")
-                .into(),
-            Equals => {
-                let comparison = self.builder.build_int_compare(
-                    IntPredicate::EQ,
-                    lval,
-                    rval,
-                    "tmpcmp",
-                );
-                self.builder
-                    .build_unsigned_int_to_float(
-                        comparison,
-                        self.context.f64_type(),
-                        "tmpbool",
-                    )
+        let predicate = match operation.value {
+            Add => {
+                return self.builder.build_int_add(lval, rval, "tmpadd").into()
+            }
+            Subtract => {
+                return self.builder.build_int_sub(lval, rval, "tmpsub").into()
+            }
+            Multiply => {
+                return self.builder.build_int_mul(lval, rval, "tmpmul").into()
+            }
+            Divide if is_signed => {
+                return self
+                    .builder
+                    .build_int_signed_div(lval, rval, "tmpdiv")
                     .into()
             }
-            NotEquals => {
-                let comparison = self.builder.build_int_compare(
-                    IntPredicate::NE,
-                    lval,
+            Divide => {
+                return self
+                    .builder
+                    .build_int_unsigned_div(lval, rval, "tmpdiv")
+                    .into()
+            }
+            BitwiseAnd => {
+                return self.builder.build_and(lval, rval, "tmpand").into()
+            }
+            BitwiseOr => {
+                return self.builder.build_or(lval, rval, "tmpor").into()
+            }
+            BitwiseXor => {
+                return self.builder.build_xor(lval, rval, "tmpxor").into()
+            }
+            ShiftLeft => {
+                return self
+                    .builder
+                    .build_left_shift(lval, rval, "tmpshl")
+                    .into()
+            }
+            // Signed values are shifted arithmetically to keep their sign
+            ShiftRight => {
+                return self
+                    .builder
+                    .build_right_shift(lval, rval, is_signed, "tmpshr")
+                    .into()
+            }
+            Equals => IntPredicate::EQ,
+            NotEquals => IntPredicate::NE,
+            GreaterThan if is_signed => IntPredicate::SGT,
+            GreaterThan => IntPredicate::UGT,
+            LessThan if is_signed => IntPredicate::SLT,
+            LessThan => IntPredicate::ULT,
+        };
+        self.builder
+            .build_int_compare(predicate, lval, rval, "tmpcmp")
+            .into()
+    }
+
+    /// Adds the runtime checks for an integer operation, which panic with
+    /// the location of the operation if it overflows, divides by zero or
+    /// shifts by more than the width of the integer.
+    ///
+    /// Addition, subtraction and multiplication are computed by the
+    /// `*.with.overflow` intrinsics, so their result is returned here.
+    fn build_checked_int_operation(
+        &self,
+        lval: IntValue<'c>,
+        rval: IntValue<'c>,
+        operation: &Spanned<Operation>,
+        is_signed: bool,
+    ) -> Option<BasicValueEnum<'c>> {
+        use Operation::*;
+        let int_type = lval.get_type();
+        let width = int_type.get_bit_width();
+        let location = self.locations.describe(operation.span.0);
+        let (intrinsic, description) = match operation.value {
+            Add => ("add", "addition"),
+            Subtract => ("sub", "subtraction"),
+            Multiply => ("mul", "multiplication"),
+            Divide => {
+                let is_zero = self.builder.build_int_compare(
+                    IntPredicate::EQ,
                     rval,
-                    "tmpcmp",
+                    int_type.const_zero(),
+                    "tmpiszero",
                 );
-                self.builder
-                    .build_unsigned_int_to_float(
-                        comparison,
-                        self.context.f64_type(),
-                        "tmpbool",
-                    )
-                    .into()
+                self.build_panic_if(
+                    is_zero,
+                    &format!("Division by zero at {}", location),
+                );
+                // The only signed division that overflows is `MIN / -1`
+                if is_signed {
+                    let min = int_type.const_int(1 << (width - 1), false);
+                    let is_min = self.builder.build_int_compare(
+                        IntPredicate::EQ,
+                        lval,
+                        min,
+                        "tmpismin",
+                    );
+                    let is_minus_one = self.builder.build_int_compare(
+                        IntPredicate::EQ,
+                        rval,
+                        int_type.const_int(u64::MAX, false),
+                        "tmpisminusone",
+                    );
+                    let overflows =
+                        self.builder.build_and(is_min, is_minus_one, "tmpand");
+                    self.build_panic_if(
+                        overflows,
+                        &format!(
+                            "Integer overflow in division at {}",
+                            location
+                        ),
+                    );
+                }
+                return None;
             }
-            GreaterThan => {
-                let comparison = self.builder.build_int_compare(
-                    IntPredicate::SGT,
-                    lval,
+            ShiftLeft | ShiftRight => {
+                let is_out_of_range = self.builder.build_int_compare(
+                    IntPredicate::UGE,
                     rval,
-                    "tmpcmp",
+                    int_type.const_int(width.into(), false),
+                    "tmpisoutofrange",
+                );
+                self.build_panic_if(
+                    is_out_of_range,
+                    &format!("Shift amount out of range at {}", location),
                 );
+                return None;
+            }
+            _ => return None,
+        };
+        Some(self.build_overflow_checked(
+            lval,
+            rval,
+            intrinsic,
+            is_signed,
+            &format!("Integer overflow in {} at {}", description, location),
+        ))
+    }
+
+    /// Adds, subtracts or multiplies (`intrinsic` is `add`, `sub` or `mul`)
+    /// with LLVM's `*.with.overflow` intrinsics, and panics with `message`
+    /// if the result overflows
+    fn build_overflow_checked(
+        &self,
+        lval: IntValue<'c>,
+        rval: IntValue<'c>,
+        intrinsic: &str,
+        is_signed: bool,
+        message: &str,
+    ) -> BasicValueEnum<'c> {
+        let int_type = lval.get_type();
+        let name = format!(
+            "llvm.{}{}.with.overflow.i{}",
+            if is_signed { "s" } else { "u" },
+            intrinsic,
+            int_type.get_bit_width()
+        );
+        let function = self.get_function(&name).unwrap_or_else(|| {
+            let result_type = self.context.struct_type(
+                &[int_type.into(), self.context.bool_type().into()],
+                false,
+            );
+            self.module.add_function(
+                &name,
+                result_type.fn_type(&[int_type.into(), int_type.into()], false),
+                None,
+            )
+        });
+        let result = self
+            .function_call(function, &[lval.into(), rval.into()])
+            .unwrap()
+            .into_struct_value();
+        let overflows = self
+            .builder
+            .build_extract_value(result, 1, "tmpoverflows")
+            .unwrap()
+            .into_int_value();
+        self.build_panic_if(overflows, message);
+        self.builder
+            .build_extract_value(result, 0, "tmpresult")
+            .unwrap()
+    }
+
+    /// Branches to a call to `rox_panic` with the given message if
+    /// `condition` is true, and otherwise continues in a new block
+    fn build_panic_if(&self, condition: IntValue<'c>, message: &str) {
+        let panic_block = self.append_basic_block("panic");
+        let continue_block = self.append_basic_block("checked");
+        self.builder.build_conditional_branch(
+            condition,
+            panic_block,
+            continue_block,
+        );
+        self.builder.position_at_end(panic_block);
+        let message = self.string_literal(message);
+        self.call_runtime(runtime::PANIC, &[message]);
+        self.builder.build_unreachable();
+        self.builder.position_at_end(continue_block);
+    }
+
+    /// Negation is only defined for signed integers, so with overflow
+    /// checks it's computed as a checked `0 - value`, which panics when
+    /// negating the minimum of the type
+    pub fn build_unary(
+        &self,
+        value: BasicValueEnum<'c>,
+        unary: &Spanned<Unary>,
+    ) -> BasicValueEnum<'c> {
+        match (&unary.value, value) {
+            (Unary::Negate, BasicValueEnum::FloatValue(float)) => {
+                self.builder.build_float_neg(float, "tmpneg").into()
+            }
+            (Unary::Negate, BasicValueEnum::IntValue(int))
+                if self.overflow_checks =>
+            {
+                self.build_overflow_checked(
+                    int.get_type().const_zero(),
+                    int,
+                    "sub",
+                    true,
+                    &format!(
+                        "Integer overflow in negation at {}",
+                        self.locations.describe(unary.span.0)
+                    ),
+                )
+            }
+            (Unary::Negate, BasicValueEnum::IntValue(int)) => {
+                self.builder.build_int_neg(int, "tmpneg").into()
+            }
+            // `Bool`s are `i1`s, so logical and bitwise not are the same
+            (Unary::Not, BasicValueEnum::IntValue(int))
+            | (Unary::BitwiseNot, BasicValueEnum::IntValue(int)) => {
+                self.builder.build_not(int, "tmpnot").into()
+            }
+            _ => unreachable!(
+                "Unary operands should be checked during type checking"
+            ),
+        }
+    }
+
+    /// Converts a value with `as`. The type checker only allows casts
+    /// between numbers, between `Char`s and integers and from `Bool`s
+    /// to integers, so the value is always either an integer or a float.
+    ///
+    /// Integers that aren't Unicode scalar values panic when they're
+    /// cast to `Char`, with the location of the cast's type at `span`.
+    pub fn build_cast(
+        &self,
+        value: BasicValueEnum<'c>,
+        from: &TypeConstructor,
+        to: &TypeConstructor,
+        target_type: BasicTypeEnum<'c>,
+        span: &Span,
+    ) -> BasicValueEnum<'c> {
+        let result = self.build_conversion(value, from, to, target_type);
+        // Every `UInt8` is a valid `Char`
+        if from.is_integer()
+            && *from != TypeConstructor::UInt8
+            && *to == TypeConstructor::Char
+        {
+            self.build_char_check(result.into_int_value(), span);
+        }
+        result
+    }
+
+    /// Panics unless `code` is a Unicode scalar value, which is
+    /// any code point that isn't a surrogate
+    fn build_char_check(&self, code: IntValue<'c>, span: &Span) {
+        let int_type = code.get_type();
+        let surrogate_offset = self.builder.build_int_sub(
+            code,
+            int_type.const_int(0xD800, false),
+            "tmpsurrogateoffset",
+        );
+        let is_surrogate = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            surrogate_offset,
+            int_type.const_int(0x800, false),
+            "tmpissurrogate",
+        );
+        let is_too_large = self.builder.build_int_compare(
+            IntPredicate::UGT,
+            code,
+            int_type.const_int(std::char::MAX as u64, false),
+            "tmpistoolarge",
+        );
+        let is_invalid =
+            self.builder
+                .build_or(is_surrogate, is_too_large, "tmpisinvalid");
+        self.build_panic_if(
+            is_invalid,
+            &format!(
+                "Cast to Char of an invalid Unicode scalar value at {}",
+                self.locations.describe(span.0)
+            ),
+        );
+    }
+
+    fn build_conversion(
+        &self,
+        value: BasicValueEnum<'c>,
+        from: &TypeConstructor,
+        to: &TypeConstructor,
+        target_type: BasicTypeEnum<'c>,
+    ) -> BasicValueEnum<'c> {
+        // `Char`s and `Bool`s are never negative
+        let is_signed = from.is_signed();
+        match (value, target_type) {
+            (
+                BasicValueEnum::IntValue(int),
+                BasicTypeEnum::IntType(int_type),
+            ) => {
+                let from_width = int.get_type().get_bit_width();
+                let to_width = int_type.get_bit_width();
+                if from_width < to_width && is_signed {
+                    self.builder
+                        .build_int_s_extend(int, int_type, "tmpcast")
+                        .into()
+                } else if from_width < to_width {
+                    self.builder
+                        .build_int_z_extend(int, int_type, "tmpcast")
+                        .into()
+                } else if from_width > to_width {
+                    self.builder
+                        .build_int_truncate(int, int_type, "tmpcast")
+                        .into()
+                } else {
+                    int.into()
+                }
+            }
+            (
+                BasicValueEnum::IntValue(int),
+                BasicTypeEnum::FloatType(float_type),
+            ) => {
+                if is_signed {
+                    self.builder
+                        .build_signed_int_to_float(int, float_type, "tmpcast")
+                        .into()
+                } else {
+                    self.builder
+                        .build_unsigned_int_to_float(int, float_type, "tmpcast")
+                        .into()
+                }
+            }
+            (
+                BasicValueEnum::FloatValue(float),
+                BasicTypeEnum::IntType(int_type),
+            ) => self
+                .build_saturating_float_to_int(float, int_type, to.is_signed())
+                .into(),
+            (
+                BasicValueEnum::FloatValue(float),
+                BasicTypeEnum::FloatType(float_type),
+            ) => {
+                use TypeConstructor::{Float, Float32};
+                if (from, to) == (&Float32, &Float) {
+                    self.builder
+                        .build_float_ext(float, float_type, "tmpcast")
+                        .into()
+                } else if (from, to) == (&Float, &Float32) {
+                    self.builder
+                        .build_float_trunc(float, float_type, "tmpcast")
+                        .into()
+                } else {
+                    float.into()
+                }
+            }
+            _ => unreachable!("Casts should be checked during type checking"),
+        }
+    }
+
+    /// Converts a float to an integer, saturating at the bounds of the
+    /// integer type and converting NaN to `0`, as with Rust's `as` and the
+    /// constant evaluator. A bare `fptosi` or `fptoui` gives a poison
+    /// value for floats that are out of range.
+    fn build_saturating_float_to_int(
+        &self,
+        float: FloatValue<'c>,
+        int_type: IntType<'c>,
+        is_signed: bool,
+    ) -> IntValue<'c> {
+        let width = int_type.get_bit_width();
+        let float_type = float.get_type();
+        let (converted, min, max, lower_bound, upper_bound) = if is_signed {
+            (
+                self.builder
+                    .build_float_to_signed_int(float, int_type, "tmpcast"),
+                int_type.const_int(1 << (width - 1), false),
+                int_type.const_int(u64::MAX >> (65 - width), false),
+                -(2f64.powi(width as i32 - 1)),
+                2f64.powi(width as i32 - 1),
+            )
+        } else {
+            (
                 self.builder
-                    .build_unsigned_int_to_float(
-                        comparison,
-                        self.context.f64_type(),
-                        "tmpbool",
+                    .build_float_to_unsigned_int(float, int_type, "tmpcast"),
+                int_type.const_zero(),
+                int_type.const_int(u64::MAX, false),
+                0.0,
+                2f64.powi(width as i32),
+            )
+        };
+        // The bounds are powers of two, which every float type can represent
+        let is_too_small = self.builder.build_float_compare(
+            FloatPredicate::OLT,
+            float,
+            float_type.const_float(lower_bound),
+            "tmpistoosmall",
+        );
+        let is_too_large = self.builder.build_float_compare(
+            FloatPredicate::OGE,
+            float,
+            float_type.const_float(upper_bound),
+            "tmpistoolarge",
+        );
+        let is_nan = self.builder.build_float_compare(
+            FloatPredicate::UNO,
+            float,
+            float,
+            "tmpisnan",
+        );
+        let saturated = self.builder.build_select(
+            is_too_small,
+            min,
+            converted,
+            "tmpsaturated",
+        );
+        let saturated = self.builder.build_select(
+            is_too_large,
+            max.into(),
+            saturated,
+            "tmpsaturated",
+        );
+        self.builder
+            .build_select(
+                is_nan,
+                int_type.const_zero().into(),
+                saturated,
+                "tmpsaturated",
+            )
+            .into_int_value()
+    }
+
+    pub fn build_string_operation(
+        &self,
+        lval: StructValue<'c>,
+        rval: StructValue<'c>,
+        operation: &Operation,
+    ) -> BasicValueEnum<'c> {
+        use Operation::*;
+        let predicate = match operation {
+            Add => {
+                return self
+                    .call_runtime(
+                        runtime::STRING_CONCAT,
+                        &[lval.into(), rval.into()],
                     )
-                    .into()
+                    .unwrap()
             }
-            LessThan => {
-                let comparison = self.builder.build_int_compare(
-                    IntPredicate::SLT,
-                    lval,
-                    rval,
-                    "tmpcmp",
+            Equals => IntPredicate::EQ,
+            NotEquals => IntPredicate::NE,
+            GreaterThan => IntPredicate::SGT,
+            LessThan => IntPredicate::SLT,
+            Subtract | Multiply | Divide | BitwiseAnd | BitwiseOr
+            | BitwiseXor | ShiftLeft | ShiftRight => unreachable!(
+                "Strings only support `+` and comparisons, which should be checked during type checking"
+            ),
+        };
+        let ordering = self
+            .call_runtime(runtime::STRING_COMPARE, &[lval.into(), rval.into()])
+            .unwrap()
+            .into_int_value();
+        self.builder
+            .build_int_compare(
+                predicate,
+                ordering,
+                self.context.i32_type().const_zero(),
+                "tmpcmp",
+            )
+            .into()
+    }
+
+    /// Converts a value to a string for string interpolation
+    pub fn build_format(
+        &self,
+        value: BasicValueEnum<'c>,
+        type_: &Type,
+    ) -> BasicValueEnum<'c> {
+        let format_function = match type_ {
+            Type::Apply(TypeConstructor::String, _) => return value,
+            Type::Apply(TypeConstructor::Int, _) => runtime::FORMAT_INT,
+            Type::Apply(TypeConstructor::Float, _) => runtime::FORMAT_FLOAT,
+            Type::Apply(TypeConstructor::Bool, _) => runtime::FORMAT_BOOL,
+            Type::Apply(TypeConstructor::Char, _) => runtime::FORMAT_CHAR,
+            // Other numbers are widened to the largest type of their kind
+            Type::Apply(constructor, _) if constructor.is_float() => {
+                let value = self.builder.build_float_ext(
+                    value.into_float_value(),
+                    self.context.f64_type(),
+                    "tmpext",
                 );
-                self.builder
-                    .build_unsigned_int_to_float(
-                        comparison,
-                        self.context.f64_type(),
-                        "tmpbool",
+                return self
+                    .call_runtime(runtime::FORMAT_FLOAT, &[value.into()])
+                    .unwrap();
+            }
+            Type::Apply(constructor, _) if constructor.is_integer() => {
+                let i64_type = self.context.i64_type();
+                let (value, format_function) = if constructor.is_signed() {
+                    (
+                        self.builder.build_int_s_extend_or_bit_cast(
+                            value.into_int_value(),
+                            i64_type,
+                            "tmpext",
+                        ),
+                        runtime::FORMAT_INT64,
                     )
-                    .into()
+                } else {
+                    (
+                        self.builder.build_int_z_extend_or_bit_cast(
+                            value.into_int_value(),
+                            i64_type,
+                            "tmpext",
+                        ),
+                        runtime::FORMAT_UINT64,
+                    )
+                };
+                return self
+                    .call_runtime(format_function, &[value.into()])
+                    .unwrap();
             }
-        }
+            _ => unreachable!(
+                "Interpolated values should be checked during type checking"
+            ),
+        };
+        self.call_runtime(format_function, &[value]).unwrap()
+    }
+
+    /// Calls one of the functions emitted by the `runtime` module
+    pub fn call_runtime(
+        &self,
+        name: &str,
+        args: &[BasicValueEnum<'c>],
+    ) -> Option<BasicValueEnum<'c>> {
+        let function = self.get_function(name).unwrap_or_else(|| {
+            panic!("Runtime function {} was not added to the module", name)
+        });
+        self.function_call(function, args)
     }
 
     pub fn build_float_operation(
@@ -351,76 +1065,44 @@ impl<'f, 'c> CompilerState<'f, 'c> {
         operation: &Operation,
     ) -> BasicValueEnum<'c> {
         use Operation::*;
-        match operation {
-            Add => self.builder.build_float_add(lval, rval, "tmpadd").into(),
-            Subtract => {
-                self.builder.build_float_sub(lval, rval, "tmpsub").into()
-            }
-            Multiply => {
-                self.builder.build_float_mul(lval, rval, "tmpmul").into()
-            }
-            Divide => self.builder.build_float_div(lval, rval, "tmpdiv").into(),
-            Equals => {
-                let comparison = self.builder.build_float_compare(
-                    FloatPredicate::OEQ,
-                    lval,
-                    rval,
-                    "tmpcmp",
-                );
-                self.builder
-                    .build_unsigned_int_to_float(
-                        comparison,
-                        self.context.f64_type(),
-                        "tmpbool",
-                    )
+        let predicate = match operation {
+            Add => {
+                return self
+                    .builder
+                    .build_float_add(lval, rval, "tmpadd")
                     .into()
             }
-            NotEquals => {
-                let comparison = self.builder.build_float_compare(
-                    FloatPredicate::ONE,
-                    lval,
-                    rval,
-                    "tmpcmp",
-                );
-                self.builder
-                    .build_unsigned_int_to_float(
-                        comparison,
-                        self.context.f64_type(),
-                        "tmpbool",
-                    )
+            Subtract => {
+                return self
+                    .builder
+                    .build_float_sub(lval, rval, "tmpsub")
                     .into()
             }
-            GreaterThan => {
-                let comparison = self.builder.build_float_compare(
-                    FloatPredicate::OGT,
-                    lval,
-                    rval,
-                    "tmpcmp",
-                );
-                self.builder
-                    .build_unsigned_int_to_float(
-                        comparison,
-                        self.context.f64_type(),
-                        "tmpbool",
-                    )
+            Multiply => {
+                return self
+                    .builder
+                    .build_float_mul(lval, rval, "tmpmul")
                     .into()
             }
-            LessThan => {
-                let comparison = self.builder.build_float_compare(
-                    FloatPredicate::OLT,
-                    lval,
-                    rval,
-                    "tmpcmp",
-                );
-                self.builder
-                    .build_unsigned_int_to_float(
-                        comparison,
-                        self.context.f64_type(),
-                        "tmpbool",
-                    )
+            Divide => {
+                return self
+                    .builder
+                    .build_float_div(lval, rval, "tmpdiv")
                     .into()
             }
-        }
+            BitwiseAnd | BitwiseOr | BitwiseXor | ShiftLeft | ShiftRight => {
+                unreachable!(
+                    "Bitwise operations on floats should be rejected during type checking"
+                )
+            }
+            Equals => FloatPredicate::OEQ,
+            NotEquals => FloatPredicate::ONE,
+            GreaterThan => FloatPredicate::OGT,
+            LessThan => FloatPredicate::OLT,
+        };
+        self.builder
+            .build_float_compare(predicate, lval, rval, "tmpcmp")
+            .into()
     }
 
     pub fn function_call(
@@ -428,14 +1110,38 @@ impl<'f, 'c> CompilerState<'f, 'c> {
         function: FunctionValue<'c>,
         args: &[BasicValueEnum<'c>],
     ) -> Option<BasicValueEnum<'c>> {
-        self.builder
+        // Strings are passed to `extern` functions as C strings,
+        // so we hand over the pointer to the NUL-terminated bytes
+        let param_types = function.get_type().get_param_types();
+        let args = args
+            .iter()
+            .zip(param_types.iter())
+            .map(|(arg, param_type)| {
+                if param_type.is_pointer_type() && arg.is_struct_value() {
+                    self.builder
+                        .build_extract_value(arg.into_struct_value(), 0, "")
+                        .unwrap()
+                } else {
+                    *arg
+                }
+            })
+            .collect::<Vec<_>>();
+        let return_value = self
+            .builder
             .build_call(
                 function,
                 &args.iter().map(|a| (*a).into()).collect::<Vec<_>>(),
                 "tmp",
             )
             .try_as_basic_value()
-            .left()
+            .left();
+        match return_value {
+            // The only pointers we get back are C strings from `extern`
+            // functions, which we wrap back up into a Rox string
+            Some(BasicValueEnum::PointerValue(pointer)) => self
+                .call_runtime(runtime::STRING_FROM_C_STRING, &[pointer.into()]),
+            value => value,
+        }
     }
 
     pub fn position_at_end(&self, block: BasicBlock) {
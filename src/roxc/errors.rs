@@ -1,12 +1,14 @@
-use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle};
 use codespan_reporting::files::SimpleFile;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
 use codespan_reporting::term::Config;
-use lalrpop_util::lexer::Token;
 use lalrpop_util::{ErrorRecovery, ParseError};
-use std::fs::read_to_string;
-use std::path::PathBuf;
+use std::fmt::{self, Display, Formatter};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
+use super::parser::lexer::{LexicalError, Token};
 use super::{Span, Spanned};
 
 /// Rox's custom `Result` type
@@ -25,50 +27,73 @@ fn clean_expected(expected: &[String]) -> Vec<String> {
         .collect::<Vec<_>>()
 }
 
+/// A diagnostic about a Rox program, which is printed with `codespan`
 #[derive(Clone, Debug)]
 pub struct RoxError {
+    /// The file that the labels point into
     pub file: SimpleFile<String, String>,
+    /// A summary of the error
     pub message: Option<String>,
+    /// The parts of the source that caused the error
     pub labels: Vec<Label<()>>,
+    /// Extra information, such as how to fix the error
     pub notes: Vec<String>,
 }
 
 impl RoxError {
-    pub fn new<T: Clone + Into<PathBuf>>(file: T, message: &str) -> Self {
-        let contents = read_to_string(dbg!(file.clone().into())).unwrap();
+    /// Adds a label for the code that caused the error
+    pub fn with_label(self, message: &str, span: Span) -> RoxError {
+        let mut labels = self.labels;
+        labels.push(Label::primary((), span.0..span.1).with_message(message));
+        RoxError { labels, ..self }
+    }
+
+    /// Adds a label for related code, such as where a variable was declared
+    pub fn with_secondary_label(self, message: &str, span: Span) -> RoxError {
+        let mut labels = self.labels;
+        labels.push(Label::secondary((), span.0..span.1).with_message(message));
+        RoxError { labels, ..self }
+    }
+
+    /// Creates an error for a file that isn't known where the error is
+    /// raised, such as during type checking. The caller sets the file
+    /// with [`RoxError::in_file`] before the error is emitted.
+    pub fn with_file_placeholder(message: &str) -> Self {
         RoxError {
-            file: SimpleFile::new(
-                file.into().to_str().unwrap().to_string(),
-                contents,
-            ),
+            file: SimpleFile::new(String::new(), String::new()),
             message: Some(message.to_string()),
             labels: Vec::new(),
             notes: Vec::new(),
         }
     }
 
-    pub fn with_label(self, message: &str, span: Span) -> RoxError {
-        let mut labels = self.labels;
-        labels.push(Label::primary((), span.0..span.1).with_message(message));
-        RoxError { labels, ..self }
+    /// Sets the file that the labels of this error point into
+    pub fn in_file(self, path: &Path, source: &str) -> RoxError {
+        RoxError {
+            file: SimpleFile::new(
+                path.display().to_string(),
+                source.to_string(),
+            ),
+            ..self
+        }
     }
 
-    pub fn with_file_placeholder(message: &str) -> Self {
-        let path = PathBuf::from("./examples/array.rox");
-        Self::new(path, message)
+    /// Adds a note to the end of the error
+    pub fn with_note(self, note: &str) -> RoxError {
+        let mut notes = self.notes;
+        notes.push(note.to_string());
+        RoxError { notes, ..self }
     }
 
-    pub fn from_error_recoveries<T: Clone + Into<PathBuf>>(
+    /// Combines the errors that the parser recovered from into one error
+    pub fn from_error_recoveries(
         error_vec: Vec<
             Spanned<
-                lalrpop_util::ErrorRecovery<
-                    usize,
-                    lalrpop_util::lexer::Token<'_>,
-                    &'static str,
-                >,
+                lalrpop_util::ErrorRecovery<usize, Token<'_>, LexicalError>,
             >,
         >,
-        file: T,
+        path: &Path,
+        source: &str,
     ) -> Self {
         let rox_errors: Vec<RoxError> = error_vec
             .iter()
@@ -77,13 +102,10 @@ impl RoxError {
                      value: ErrorRecovery { error, .. },
                      span,
                  }| {
-                    RoxError::from_parse_error(
-                        Spanned {
-                            span: span.clone(),
-                            value: error.clone(),
-                        },
-                        file.clone().into(),
-                    )
+                    RoxError::from_parse_error(Spanned {
+                        span: span.clone(),
+                        value: error.clone(),
+                    })
                 },
             )
             .collect();
@@ -93,8 +115,8 @@ impl RoxError {
             .collect::<Vec<_>>();
         RoxError {
             file: SimpleFile::new(
-                file.clone().into().to_str().unwrap().to_string(),
-                read_to_string(file.into()).unwrap(),
+                path.display().to_string(),
+                source.to_string(),
             ),
             message: Some(String::from(
                 "Encountered the following errors while parsing",
@@ -104,13 +126,12 @@ impl RoxError {
         }
     }
 
+    /// Describes a single parse error. The caller sets the file
+    /// with [`RoxError::in_file`] before the error is emitted.
     pub fn from_parse_error(
-        error: Spanned<ParseError<usize, Token<'_>, &'static str>>,
-        file: PathBuf,
+        error: Spanned<ParseError<usize, Token<'_>, LexicalError>>,
     ) -> Self {
-        let file_source = read_to_string(file.clone()).unwrap();
-        let file =
-            SimpleFile::new(String::from(file.to_str().unwrap()), file_source);
+        let file = SimpleFile::new(String::new(), String::new());
         match error.value {
             ParseError::InvalidToken { .. } => RoxError {
                 file,
@@ -150,18 +171,27 @@ impl RoxError {
                 labels: vec![Label::primary((), start..end)],
                 notes: Vec::new(),
             },
-            // Customer user error message
-            // Rox doesn't emit these yet
-            ParseError::User { error } => RoxError {
+            // Custom errors that are raised from grammar actions,
+            // such as invalid escape sequences in string literals
+            ParseError::User { error: message } => RoxError {
                 file,
-                message: Some(String::from(error)),
-                labels: Vec::new(),
+                message: None,
+                labels: vec![Label::primary((), error.span.0..error.span.1)
+                    .with_message(message.to_string())],
                 notes: Vec::new(),
             },
         }
     }
 
-    pub fn emit_error(&self) -> std::io::Result<()> {
+    /// Prints the error to stderr
+    pub fn emit_error(
+        &self,
+        options: &DiagnosticOptions,
+    ) -> std::io::Result<()> {
+        if options.format == ErrorFormat::Json {
+            writeln!(std::io::stderr(), "{}", self.to_json())?;
+            return Ok(());
+        }
         let mut diagnostic: Diagnostic<()> = Diagnostic::error()
             .with_labels(self.labels.clone())
             .with_notes(self.notes.clone());
@@ -169,7 +199,7 @@ impl RoxError {
             diagnostic = diagnostic.clone().with_message(message);
         }
 
-        let mut writer = StandardStream::stderr(ColorChoice::Always);
+        let mut writer = StandardStream::stderr(options.color.color_choice());
         let config = Config::default();
 
         codespan_reporting::term::emit(
@@ -179,4 +209,343 @@ impl RoxError {
             &diagnostic,
         )
     }
+
+    /// Serializes the error as a single line of JSON for `--error-format=json`.
+    /// Each label has its byte range and its one-based line and column
+    /// range, where columns count bytes like the byte range does.
+    pub fn to_json(&self) -> String {
+        let locations =
+            SourceLocations::new(self.file.name(), self.file.source());
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                let (line_start, column_start) =
+                    locations.line_and_column(label.range.start);
+                let (line_end, column_end) =
+                    locations.line_and_column(label.range.end);
+                format!(
+                    "{{\"message\":{},\"primary\":{},\"byte_start\":{},\"byte_end\":{},\"line_start\":{},\"column_start\":{},\"line_end\":{},\"column_end\":{}}}",
+                    json_string(&label.message),
+                    label.style == LabelStyle::Primary,
+                    label.range.start,
+                    label.range.end,
+                    line_start,
+                    column_start,
+                    line_end,
+                    column_end
+                )
+            })
+            .collect::<Vec<_>>();
+        let notes = self
+            .notes
+            .iter()
+            .map(|note| json_string(note))
+            .collect::<Vec<_>>();
+        // Parse errors only describe themselves in their labels
+        let message = self
+            .message
+            .as_ref()
+            .or_else(|| self.labels.first().map(|label| &label.message))
+            .map_or("", String::as_str);
+        let file = match self.file.name().as_str() {
+            "" => "null".to_string(),
+            name => json_string(name),
+        };
+        format!(
+            "{{\"severity\":\"error\",\"message\":{},\"file\":{},\"labels\":[{}],\"notes\":[{}]}}",
+            json_string(message),
+            file,
+            labels.join(","),
+            notes.join(",")
+        )
+    }
+}
+
+/// Quotes and escapes a string for JSON
+fn json_string(string: &str) -> String {
+    let mut json = String::with_capacity(string.len() + 2);
+    json.push('"');
+    for character in string.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            control if control.is_control() => {
+                json.push_str(&format!("\\u{:04x}", control as u32))
+            }
+            character => json.push(character),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// How diagnostics are printed, chosen with `--error-format`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Annotated snippets of the source, for people
+    #[default]
+    Human,
+    /// One JSON object per line for each diagnostic, for tools such as CI
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> std::result::Result<Self, Self::Err> {
+        match format {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!(
+                "Unknown error format `{}`, expected human or json",
+                format
+            )),
+        }
+    }
+}
+
+/// Whether human readable diagnostics are colored, chosen with `--color`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Colors diagnostics when stderr is a terminal, unless the
+    /// `NO_COLOR` environment variable is set
+    #[default]
+    Auto,
+    /// Always colors diagnostics
+    Always,
+    /// Never colors diagnostics
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> std::result::Result<Self, Self::Err> {
+        match mode {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!(
+                "Unknown color mode `{}`, expected auto, always or never",
+                mode
+            )),
+        }
+    }
+}
+
+impl ColorMode {
+    fn color_choice(self) -> ColorChoice {
+        match self {
+            ColorMode::Always => ColorChoice::Always,
+            ColorMode::Never => ColorChoice::Never,
+            // See https://no-color.org
+            ColorMode::Auto
+                if std::env::var_os("NO_COLOR")
+                    .is_some_and(|value| !value.is_empty()) =>
+            {
+                ColorChoice::Never
+            }
+            // `termcolor` still checks `TERM`, but leaves checking
+            // for a terminal to us
+            ColorMode::Auto if std::io::stderr().is_terminal() => {
+                ColorChoice::Auto
+            }
+            ColorMode::Auto => ColorChoice::Never,
+        }
+    }
+}
+
+/// How diagnostics are printed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiagnosticOptions {
+    /// Human readable or JSON diagnostics
+    pub format: ErrorFormat,
+    /// Whether human readable diagnostics are colored
+    pub color: ColorMode,
+}
+
+/// The ways that building, checking or running a Rox program can fail.
+/// The CLI exits with a different code for each of them
+/// (see [`Error::exit_code`]).
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// A file couldn't be read or written
+    Io(RoxError),
+    /// The program has syntax errors
+    Parse(RoxError),
+    /// The program is syntactically valid, but doesn't type check
+    Type(Vec<RoxError>),
+    /// LLVM couldn't generate code for the program or its target
+    Codegen(RoxError),
+    /// An external tool, such as the linker or `lli`, couldn't be run or failed
+    Toolchain(RoxError),
+    /// The compiler panicked, which is a bug in the compiler
+    Internal(RoxError),
+}
+
+impl Error {
+    /// The code that `rox` exits with for this kind of error:
+    ///
+    /// | Code | Error                |
+    /// |------|----------------------|
+    /// | `2`  | [`Error::Parse`]     |
+    /// | `3`  | [`Error::Type`]      |
+    /// | `4`  | [`Error::Codegen`]   |
+    /// | `5`  | [`Error::Toolchain`] |
+    /// | `6`  | [`Error::Io`]        |
+    /// | `7`  | [`Error::Internal`]  |
+    ///
+    /// `1` is left for invalid command line arguments.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Parse(_) => 2,
+            Error::Type(_) => 3,
+            Error::Codegen(_) => 4,
+            Error::Toolchain(_) => 5,
+            Error::Io(_) => 6,
+            Error::Internal(_) => 7,
+        }
+    }
+
+    /// The diagnostics that describe the error
+    pub fn diagnostics(&self) -> &[RoxError] {
+        match self {
+            Error::Type(errors) => errors,
+            Error::Io(error)
+            | Error::Parse(error)
+            | Error::Codegen(error)
+            | Error::Toolchain(error)
+            | Error::Internal(error) => std::slice::from_ref(error),
+        }
+    }
+
+    /// Sets the file that the labels of every diagnostic point into
+    pub(crate) fn in_file(self, path: &Path, source: &str) -> Error {
+        let in_file = |error: RoxError| error.in_file(path, source);
+        match self {
+            Error::Io(error) => Error::Io(in_file(error)),
+            Error::Parse(error) => Error::Parse(in_file(error)),
+            Error::Type(errors) => {
+                Error::Type(errors.into_iter().map(in_file).collect())
+            }
+            Error::Codegen(error) => Error::Codegen(in_file(error)),
+            Error::Toolchain(error) => Error::Toolchain(in_file(error)),
+            Error::Internal(error) => Error::Internal(in_file(error)),
+        }
+    }
+
+    /// Prints every diagnostic to stderr
+    pub fn emit(&self, options: &DiagnosticOptions) -> std::io::Result<()> {
+        self.diagnostics()
+            .iter()
+            .try_for_each(|error| error.emit_error(options))
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Error::Io(_) => "I/O error",
+            Error::Parse(_) => "parse error",
+            Error::Type(_) => "type error",
+            Error::Codegen(_) => "code generation error",
+            Error::Toolchain(_) => "toolchain error",
+            Error::Internal(_) => "internal compiler error",
+        };
+        match self
+            .diagnostics()
+            .first()
+            .and_then(|error| error.message.as_ref())
+        {
+            Some(message) => write!(f, "{}: {}", kind, message),
+            None => write!(f, "{}", kind),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Maps byte offsets in a source file to the `file:line:column`
+/// locations reported by runtime panics, such as integer overflow
+pub struct SourceLocations {
+    path: String,
+    /// The byte offset where each line starts
+    line_starts: Vec<usize>,
+}
+
+impl SourceLocations {
+    pub fn new(path: impl Into<PathBuf>, source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        SourceLocations {
+            path: path.into().display().to_string(),
+            line_starts,
+        }
+    }
+
+    /// The one-based line and column of the given byte offset
+    pub fn line_and_column(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        (line + 1, offset - self.line_starts[line] + 1)
+    }
+
+    pub fn describe(&self, offset: usize) -> String {
+        let (line, column) = self.line_and_column(offset);
+        format!("{}:{}:{}", self.path, line, column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_describes_source_locations() {
+        let locations =
+            SourceLocations::new("main.rox", "fn main() do\n    1 + 2;\nend");
+
+        assert_eq!(locations.describe(0), "main.rox:1:1");
+        assert_eq!(locations.describe(19), "main.rox:2:7");
+        assert_eq!(locations.describe(13), "main.rox:2:1");
+    }
+
+    #[test]
+    fn it_serializes_errors_as_json() {
+        let error = RoxError::with_file_placeholder("Unknown type `Foo`")
+            .with_label("not found in this scope", Span(24, 27))
+            .with_note("a \"quoted\"\tnote")
+            .in_file(
+                Path::new("main.rox"),
+                "fn main() do\n    let x: Foo = 1;\nend",
+            );
+
+        assert_eq!(
+            error.to_json(),
+            concat!(
+                r#"{"severity":"error","message":"Unknown type `Foo`","file":"main.rox","#,
+                r#""labels":[{"message":"not found in this scope","primary":true,"#,
+                r#""byte_start":24,"byte_end":27,"line_start":2,"column_start":12,"#,
+                r#""line_end":2,"column_end":15}],"notes":["a \"quoted\"\tnote"]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn it_serializes_errors_without_a_file_as_json() {
+        let error =
+            RoxError::with_file_placeholder("Failed to read `main.rox`");
+
+        assert_eq!(
+            error.to_json(),
+            r#"{"severity":"error","message":"Failed to read `main.rox`","file":null,"labels":[],"notes":[]}"#
+        );
+    }
 }
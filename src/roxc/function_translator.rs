@@ -1,9 +1,11 @@
 use crate::roxc::compiler_state::CompilerState;
+use crate::roxc::runtime;
 use crate::roxc::{
-    FunctionDeclaration, Identifier, TaggedExpression, TaggedStatement,
+    not_yet_supported, record_span, FunctionDeclaration, Identifier, Operation,
+    Pattern, Result, RoxError, TaggedExpression, TaggedStatement,
 };
 use inkwell::types::BasicTypeEnum;
-use inkwell::values::{BasicValueEnum, PointerValue};
+use inkwell::values::{BasicValueEnum, PointerValue, StructValue};
 use inkwell::{basic_block::BasicBlock, values::IntValue};
 use std::borrow::Borrow;
 use std::collections::HashMap;
@@ -29,24 +31,36 @@ impl<'func, 'ctx> FunctionTranslator<'func, 'ctx> {
         }
     }
 
-    pub(crate) fn translate_function(&mut self, block: &[TaggedStatement]) {
-        self.translate_block(block);
+    pub(crate) fn translate_function(
+        &mut self,
+        block: &[TaggedStatement],
+    ) -> Result<()> {
+        self.translate_block(block)
     }
 
-    fn translate_block(&mut self, block: &[TaggedStatement]) {
-        block.iter().for_each(|statement| {
-            self.translate_statement(statement);
-        })
+    fn translate_block(&mut self, block: &[TaggedStatement]) -> Result<()> {
+        block
+            .iter()
+            .try_for_each(|statement| self.translate_statement(statement))
     }
 
-    fn translate_statement(&mut self, statement: &TaggedStatement) {
+    fn translate_statement(
+        &mut self,
+        statement: &TaggedStatement,
+    ) -> Result<()> {
         match statement.borrow() {
-            TaggedStatement::StructDeclaration => {}
+            TaggedStatement::StructDeclaration
+            | TaggedStatement::ConstDeclaration => {}
+            TaggedStatement::StaticDeclaration(..) => {
+                unreachable!("Statics can only be declared at the top level")
+            }
             TaggedStatement::Expression(expression) => {
-                self.translate_expression(expression.to_owned());
+                self.translate_expression(expression.to_owned())?;
             }
             TaggedStatement::FunctionDeclaration(..) => {
-                panic!("For right now, functions can only be declared at the top level.")
+                return Err(not_yet_supported(
+                    "Declaring functions inside other functions",
+                ));
             }
             // The `extern` tag merely declares the function to the type checker
             // The linker will then try to dynamically link the function call
@@ -59,7 +73,7 @@ impl<'func, 'ctx> FunctionTranslator<'func, 'ctx> {
             TaggedStatement::Return(maybe_expression) => {
                 if let Some(expression) = maybe_expression {
                     if let Some(return_) =
-                        self.translate_expression(expression.to_owned())
+                        self.translate_expression(expression.to_owned())?
                     {
                         self.current_state.build_return(Some(&return_));
                     } else {
@@ -79,12 +93,11 @@ impl<'func, 'ctx> FunctionTranslator<'func, 'ctx> {
                 let merge_block =
                     self.current_state.append_basic_block("continue");
                 let conditional_value = self
-                    .translate_expression(conditional.as_ref().to_owned())
+                    .translate_expression(conditional.as_ref().to_owned())?
                     .expect("Cannot evaluate condition with void value")
-                    .into_float_value();
+                    .into_int_value();
                 self.current_state.build_conditional(
                     conditional_value,
-                    "ifcond",
                     if_block,
                     else_block,
                 );
@@ -93,12 +106,12 @@ impl<'func, 'ctx> FunctionTranslator<'func, 'ctx> {
                     Some(if_statements.clone()),
                     if_block,
                     merge_block,
-                );
+                )?;
                 self.read_into_block(
                     else_statements_maybe.clone(),
                     else_block,
                     merge_block,
-                );
+                )?;
 
                 self.current_state.position_at_end(merge_block);
                 // N.B. I left out the `phi` value since I don't intend
@@ -106,6 +119,7 @@ impl<'func, 'ctx> FunctionTranslator<'func, 'ctx> {
                 // change in the future
             }
         }
+        Ok(())
     }
 
     fn read_into_block(
@@ -113,84 +127,189 @@ impl<'func, 'ctx> FunctionTranslator<'func, 'ctx> {
         maybe_statements: Option<Vec<TaggedStatement>>,
         conditional_block: BasicBlock,
         merge_block: BasicBlock,
-    ) {
+    ) -> Result<()> {
         self.current_state.position_at_end(conditional_block);
         if let Some(statements) = maybe_statements {
-            self.translate_block(statements.as_slice());
+            self.translate_block(statements.as_slice())?;
         }
         self.current_state.build_fallback_branch(merge_block);
+        Ok(())
     }
 
     pub fn translate_expression(
         &mut self,
         expression: TaggedExpression,
-    ) -> Option<BasicValueEnum<'ctx>> {
-        match expression {
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        if let Some(span) = expression.span() {
+            record_span(span);
+        }
+        let value = match expression {
             TaggedExpression::Boolean(bool) => {
                 Some(self.current_state.bool_literal(bool))
             }
             TaggedExpression::FunctionCall(function_name, args, _rox_type) => {
-                if let Some(function) =
-                    self.current_state.get_function(&function_name.value)
-                {
+                // User-defined functions shadow the runtime's builtins
+                let maybe_function = self
+                    .current_state
+                    .get_function(&function_name.value)
+                    .or_else(|| {
+                        runtime::builtin_symbol(&function_name.value).and_then(
+                            |symbol| self.current_state.get_function(symbol),
+                        )
+                    });
+                if let Some(function) = maybe_function {
                     let argument_values: Vec<BasicValueEnum<'ctx>> = args
                         .iter()
                         .map(|arg| {
-                            self.translate_expression(arg.to_owned()).expect(
-                                "Cannot pass void expression as argument",
-                            )
+                            Ok(self
+                                .translate_expression(arg.to_owned())?
+                                .expect(
+                                    "Cannot pass void expression as argument",
+                                ))
                         })
-                        .collect::<Vec<_>>();
+                        .collect::<Result<Vec<_>>>()?;
 
                     self.current_state
                         .function_call(function, argument_values.as_slice())
+                } else if self.variables.contains_key(&function_name.value) {
+                    return Err(not_yet_supported(
+                        "Calling a function stored in a variable",
+                    ));
                 } else {
-                    panic!("Attempted to build a function not in this module.")
+                    return Err(RoxError::with_file_placeholder(&format!(
+                        "Cannot find function `{}`",
+                        function_name.value
+                    ))
+                    .with_label(
+                        "not found in this module",
+                        function_name.span,
+                    ));
+                }
+            }
+            TaggedExpression::Cast(expression, target_type, span) => {
+                let from = Type::from(expression.as_ref().to_owned());
+                let value = self
+                    .translate_expression(*expression)?
+                    .expect("Cannot cast void value");
+                let llvm_type = CompilerState::get_type(
+                    self.current_state.get_context(),
+                    self.current_state.get_target_data(),
+                    &target_type,
+                    self.variables,
+                )?
+                .expect("Cannot cast to void type");
+                match (from, target_type.as_ref()) {
+                    (Type::Apply(from, _), Type::Apply(to, _)) => Some(
+                        self.current_state
+                            .build_cast(value, &from, to, llvm_type, &span),
+                    ),
+                    _ => unreachable!(
+                        "Casts should be checked during type checking"
+                    ),
                 }
             }
-            TaggedExpression::Int(number) => {
-                Some(self.current_state.int_literal(number.value))
+            TaggedExpression::Char(character) => {
+                Some(self.current_state.char_literal(character.value))
+            }
+            TaggedExpression::Int(number, type_) => {
+                let int_type = CompilerState::get_type(
+                    self.current_state.get_context(),
+                    self.current_state.get_target_data(),
+                    &type_,
+                    self.variables,
+                )?
+                .expect("Numeric literals always have a type")
+                .into_int_type();
+                Some(self.current_state.int_literal(number.value, int_type))
             }
-            TaggedExpression::Float(num) => {
-                Some(self.current_state.float_literal(num.value))
+            TaggedExpression::Float(num, type_) => {
+                let float_type = CompilerState::get_type(
+                    self.current_state.get_context(),
+                    self.current_state.get_target_data(),
+                    &type_,
+                    self.variables,
+                )?
+                .expect("Numeric literals always have a type")
+                .into_float_type();
+                Some(self.current_state.float_literal(num.value, float_type))
             }
             TaggedExpression::Array(tagged_expressions, type_) => {
                 let array_pointer = self.create_array(
                     tagged_expressions,
                     type_.as_ref().to_owned(),
-                );
+                )?;
+
+                Some(self.current_state.build_load(array_pointer))
+            }
+            TaggedExpression::RepeatedArray(value, length, type_) => {
+                let value = self
+                    .translate_expression(*value)?
+                    .expect("Cannot create array from void value");
+                let llvm_type = CompilerState::get_type(
+                    self.current_state.get_context(),
+                    self.current_state.get_target_data(),
+                    &type_,
+                    self.variables,
+                )?
+                .expect("Unexpected void expression type");
+                let array_pointer = self
+                    .current_state
+                    .build_repeated_array(value, length, llvm_type);
 
                 Some(self.current_state.build_load(array_pointer))
             }
             TaggedExpression::String(string) => {
                 Some(self.current_state.string_literal(&string.value))
             }
-            TaggedExpression::Variable(name, expression, _type_) => {
+            TaggedExpression::InterpolatedString(parts) => {
+                let formatted_parts = parts
+                    .into_iter()
+                    .map(|part| {
+                        let part_type = Type::from(part.clone());
+                        let value = self
+                            .translate_expression(part)?
+                            .expect("Cannot interpolate void value");
+                        Ok(self.current_state.build_format(value, &part_type))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                formatted_parts.into_iter().fold(None, |string, part| {
+                    match string {
+                        None => Some(part),
+                        Some(string) => {
+                            Some(self.current_state.build_string_operation(
+                                string.into_struct_value(),
+                                part.into_struct_value(),
+                                &Operation::Add,
+                            ))
+                        }
+                    }
+                })
+            }
+            TaggedExpression::Variable(pattern, expression, _type_) => {
                 let value: BasicValueEnum<'ctx> = self
-                    .translate_expression(expression.as_ref().to_owned())
+                    .translate_expression(expression.as_ref().to_owned())?
                     .expect("Cannot define variable with void expression");
-                let allocation =
-                    self.current_state.store_variable(&name.value, value);
-                self.variables.insert(name.value.clone(), allocation);
+                self.bind_pattern(&pattern, value);
                 Some(value)
             }
             TaggedExpression::Identifier(name, _rox_type) => {
-                let variable = self
-                    .variables
-                    .get(&name.value)
-                    .expect("Variable not defined");
-                Some((*variable).into())
+                // Functions are the only values without a variable
+                let variable =
+                    self.variables.get(&name.value).ok_or_else(|| {
+                        not_yet_supported("Using a function as a value")
+                    })?;
+                Some(self.current_state.build_load(*variable))
             }
             TaggedExpression::Operation(lval, operation, rval, rox_type) => {
                 let left = self
-                    .translate_expression(lval.as_ref().to_owned())
+                    .translate_expression(lval.as_ref().to_owned())?
                     .expect("Cannot perform operation on void value");
                 let right = self
-                    .translate_expression(rval.as_ref().to_owned())
+                    .translate_expression(rval.as_ref().to_owned())?
                     .expect("Cannot perform operation on void value");
                 match rox_type.as_ref() {
                     Type::Apply(constructor, _) => match constructor {
-                        TypeConstructor::Float => {
+                        constructor if constructor.is_float() => {
                             let left = left.into_float_value();
                             let right = right.into_float_value();
                             Some(self.current_state.build_float_operation(
@@ -199,10 +318,23 @@ impl<'func, 'ctx> FunctionTranslator<'func, 'ctx> {
                                 &operation.value,
                             ))
                         }
-                        TypeConstructor::Int => {
+                        constructor
+                            if constructor.is_integer()
+                                || *constructor == TypeConstructor::Char =>
+                        {
                             let left = left.into_int_value();
                             let right = right.into_int_value();
                             Some(self.current_state.build_int_operation(
+                                left,
+                                right,
+                                &operation,
+                                constructor.is_signed(),
+                            ))
+                        }
+                        TypeConstructor::String => {
+                            let left = left.into_struct_value();
+                            let right = right.into_struct_value();
+                            Some(self.current_state.build_string_operation(
                                 left,
                                 right,
                                 &operation.value,
@@ -220,105 +352,244 @@ impl<'func, 'ctx> FunctionTranslator<'func, 'ctx> {
                 index_value,
                 _inner_array_type,
             ) => {
+                let is_string = matches!(
+                    Type::from(array_value.as_ref().to_owned()),
+                    Type::Apply(TypeConstructor::String, _)
+                );
                 let lval_expr = self
-                    .translate_expression(array_value.as_ref().to_owned())
-                    .unwrap()
-                    .into_pointer_value();
+                    .translate_expression(array_value.as_ref().to_owned())?
+                    .unwrap();
                 let index_value = self
-                    .translate_expression(index_value.as_ref().to_owned())
-                    .unwrap()
-                    .into_int_value();
-                let value_pointer =
-                    dbg!(self.index_array(lval_expr, index_value));
+                    .translate_expression(index_value.as_ref().to_owned())?
+                    .unwrap();
+                if is_string {
+                    return Ok(self.current_state.call_runtime(
+                        runtime::STRING_INDEX,
+                        &[lval_expr, index_value],
+                    ));
+                }
+                let value_pointer = self.index_array(
+                    lval_expr.into_struct_value(),
+                    index_value.into_int_value(),
+                );
                 Some(self.current_state.build_load(value_pointer))
             }
+            TaggedExpression::DotAccess(struct_value, field_name, _type) => {
+                let field_index = Type::from(struct_value.as_ref().to_owned())
+                    .get_record_fields()
+                    .expect("Fields are only accessed on structs after type checking")
+                    .iter()
+                    .position(|(name, _)| *name == field_name)
+                    .expect("Field should exist after type checking");
+                let struct_value = self
+                    .translate_expression(*struct_value)?
+                    .expect("Cannot access field of void value")
+                    .into_struct_value();
+                Some(
+                    self.current_state
+                        .build_field_access(struct_value, field_index as u32),
+                )
+            }
+            TaggedExpression::TupleAccess(tuple_value, index, _type) => {
+                let tuple_value = self
+                    .translate_expression(*tuple_value)?
+                    .expect("Cannot access element of void value")
+                    .into_struct_value();
+                Some(
+                    self.current_state
+                        .build_field_access(tuple_value, index.value as u32),
+                )
+            }
+            TaggedExpression::Tuple(elements, tuple_type) => {
+                let element_values = elements
+                    .into_iter()
+                    .map(|element| {
+                        Ok(self
+                            .translate_expression(element)?
+                            .expect("Tuple elements cannot be void"))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let llvm_type = CompilerState::get_type(
+                    self.current_state.get_context(),
+                    self.current_state.get_target_data(),
+                    &tuple_type,
+                    self.variables,
+                )?
+                .expect("Unexpected void tuple type");
+                Some(
+                    self.current_state
+                        .build_struct(llvm_type, element_values.as_slice()),
+                )
+            }
+            TaggedExpression::StructInstantiation(struct_type, fields) => {
+                let field_values = fields
+                    .iter()
+                    .map(|(_, field)| {
+                        Ok(self
+                            .translate_expression(field.as_ref().to_owned())?
+                            .expect("Cannot assign void value to struct field"))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let llvm_type = CompilerState::get_type(
+                    self.current_state.get_context(),
+                    self.current_state.get_target_data(),
+                    &struct_type,
+                    self.variables,
+                )?
+                .expect("Unexpected void struct type");
+                Some(
+                    self.current_state
+                        .build_struct(llvm_type, field_values.as_slice()),
+                )
+            }
             TaggedExpression::Assignment(lval, value_expr, _rox_type) => {
                 let rval = self
-                    .translate_expression(*value_expr)
+                    .translate_expression(*value_expr)?
                     .expect("Cannot assign Void to variable");
-                let pointer = self.translate_lvalue(lval.as_ref().to_owned());
+                let pointer =
+                    self.translate_lvalue(lval.as_ref().to_owned())?;
 
                 self.current_state.build_store(pointer, rval);
                 Some(rval)
             }
-            TaggedExpression::StructInstantiation(_, _)
-            | TaggedExpression::And(_, _)
-            | TaggedExpression::Or(_, _)
-            | TaggedExpression::Unary(_, _, _) => todo!(),
+            TaggedExpression::Unary(unary, expression, _) => {
+                let value = self
+                    .translate_expression(*expression)?
+                    .expect("Cannot perform operation on void value");
+                Some(self.current_state.build_unary(value, &unary))
+            }
+            TaggedExpression::And(_, _) | TaggedExpression::Or(_, _) => {
+                return Err(not_yet_supported(
+                    "`and` and `or` on values that aren't constants",
+                ));
+            }
+        };
+        Ok(value)
+    }
+
+    /// Stores a value in the variables bound by a `let` pattern,
+    /// extracting the elements of tuples that are destructured
+    fn bind_pattern(&mut self, pattern: &Pattern, value: BasicValueEnum<'ctx>) {
+        match pattern {
+            Pattern::Identifier(_, name) => {
+                let allocation =
+                    self.current_state.store_variable(&name.value, value);
+                self.variables.insert(name.value.clone(), allocation);
+            }
+            Pattern::Tuple(patterns) => {
+                let tuple_value = value.into_struct_value();
+                patterns.iter().enumerate().for_each(|(index, pattern)| {
+                    let element = self
+                        .current_state
+                        .build_field_access(tuple_value, index as u32);
+                    self.bind_pattern(pattern, element);
+                });
+            }
         }
     }
 
-    fn translate_lvalue(&mut self, lval: TaggedLValue) -> PointerValue<'ctx> {
-        match lval.0 {
+    fn translate_lvalue(
+        &mut self,
+        lval: TaggedLValue,
+    ) -> Result<PointerValue<'ctx>> {
+        let pointer = match lval.0 {
             TaggedExpression::BracketAccess(array_value, index_expr, _type) => {
-                let array_pointer = self.translate_lvalue(TaggedLValue(
-                    array_value.as_ref().to_owned(),
-                ));
+                // The array value holds a pointer to its elements, so we
+                // can write through it without needing the array's address
+                let array = self
+                    .translate_expression(array_value.as_ref().to_owned())?
+                    .unwrap()
+                    .into_struct_value();
                 let index = self
-                    .translate_expression(index_expr.as_ref().to_owned())
+                    .translate_expression(index_expr.as_ref().to_owned())?
                     .unwrap()
                     .into_int_value();
-                self.index_array(array_pointer, index)
+                self.index_array(array, index)
             }
             TaggedExpression::FunctionCall(..) => {
                 // Note for future @reese -- is this actually a correct assumption?
                 // i.e. can we confidentally assert that return arrays/structs from a function
                 // works correctly this way?
-                self.translate_expression(lval.0)
+                self.translate_expression(lval.0)?
                     .unwrap()
                     .into_pointer_value()
             }
             TaggedExpression::Identifier(ident_span, _) => {
                 *self.variables.get(&ident_span.value).unwrap()
             }
+            TaggedExpression::DotAccess(struct_value, field_name, _) => {
+                let field_index = Type::from(struct_value.as_ref().to_owned())
+                    .get_record_fields()
+                    .expect("Fields are only accessed on structs after type checking")
+                    .iter()
+                    .position(|(name, _)| *name == field_name)
+                    .expect("Field should exist after type checking");
+                let struct_pointer =
+                    self.translate_lvalue(TaggedLValue(*struct_value))?;
+                self.current_state
+                    .build_field_pointer(struct_pointer, field_index as u32)
+            }
+            TaggedExpression::TupleAccess(tuple_value, index, _) => {
+                let tuple_pointer =
+                    self.translate_lvalue(TaggedLValue(*tuple_value))?;
+                self.current_state
+                    .build_field_pointer(tuple_pointer, index.value as u32)
+            }
             TaggedExpression::Array(values, inner_type) => {
-                self.create_array(values, inner_type.as_ref().to_owned())
+                self.create_array(values, inner_type.as_ref().to_owned())?
             }
             TaggedExpression::And(_, _)
             | TaggedExpression::Boolean(_)
-            | TaggedExpression::Float(_)
-            | TaggedExpression::Int(_)
+            | TaggedExpression::Cast(_, _, _)
+            | TaggedExpression::Char(_)
+            | TaggedExpression::Float(_, _)
+            | TaggedExpression::Int(_, _)
             | TaggedExpression::Operation(_, _, _, _)
             | TaggedExpression::Or(_, _)
+            | TaggedExpression::RepeatedArray(_, _, _)
             | TaggedExpression::String(_)
+            | TaggedExpression::InterpolatedString(_)
             | TaggedExpression::StructInstantiation(_, _)
+            | TaggedExpression::Tuple(_, _)
             | TaggedExpression::Unary(_, _, _)
             | TaggedExpression::Assignment(..)
             | TaggedExpression::Variable(_, _, _) => {
                 unreachable!("Values cannot be assigned to this expression ({:?}) and should have caused errors during parsing or typechecking.", lval.0)
             }
-        }
+        };
+        Ok(pointer)
     }
 
     fn create_array(
         &mut self,
         tagged_expressions: Vec<TaggedExpression>,
         inner_type: Type,
-    ) -> PointerValue<'ctx> {
+    ) -> Result<PointerValue<'ctx>> {
         let expression_values = tagged_expressions
             .iter()
             .map(|t| {
-                self.translate_expression(t.to_owned())
-                    .expect("Cannot create array from void value")
+                Ok(self
+                    .translate_expression(t.to_owned())?
+                    .expect("Cannot create array from void value"))
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?;
         let llvm_type: BasicTypeEnum = CompilerState::get_type(
             self.current_state.get_context(),
+            self.current_state.get_target_data(),
             &inner_type,
             self.variables,
-            Some(expression_values.len()),
-        )
+        )?
         .expect("Unexpected void expression type");
 
-        self.current_state.build_array_allocation_with_values(
+        Ok(self.current_state.build_array_allocation_with_values(
             expression_values.as_slice(),
             llvm_type,
-        )
+        ))
     }
 
     fn index_array(
         &mut self,
-        lval_expr: PointerValue<'ctx>,
+        lval_expr: StructValue<'ctx>,
         index_value: IntValue<'ctx>,
     ) -> PointerValue<'ctx> {
         unsafe {
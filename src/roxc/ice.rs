@@ -0,0 +1,231 @@
+//! # Internal Compiler Errors
+//! A bug in the compiler shouldn't crash `rox` with a bare Rust panic.
+//! [`catch_panics`] runs the compiler and turns a panic into an
+//! "internal compiler error" diagnostic, which points at the last span
+//! that the compiler recorded with [`record_span`] and says which
+//! [`Phase`] the compiler was in.
+//!
+//! Language features that aren't implemented yet aren't bugs, so they
+//! are returned as regular errors built by [`not_yet_supported`].
+use crate::roxc::{Error, RoxError, Span};
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt::{self, Display, Formatter};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+/// The parts of the compiler that a panic can happen in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Parsing,
+    TypeChecking,
+    CodeGeneration,
+    Optimization,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Parsing => "parsing",
+            Phase::TypeChecking => "type checking",
+            Phase::CodeGeneration => "code generation",
+            Phase::Optimization => "optimization",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What the compiler is doing on this thread
+struct Progress {
+    phase: Phase,
+    span: Option<Span>,
+    /// Whether panics are being caught by `catch_panics`
+    catching: bool,
+    /// The panic recorded by the panic hook
+    panic: Option<Panic>,
+}
+
+struct Panic {
+    message: String,
+    location: String,
+    backtrace: Backtrace,
+}
+
+thread_local! {
+    static PROGRESS: RefCell<Progress> = const {
+        RefCell::new(Progress {
+            phase: Phase::Parsing,
+            span: None,
+            catching: false,
+            panic: None,
+        })
+    };
+}
+
+/// Records that the compiler has moved on to another phase
+pub(crate) fn enter_phase(phase: Phase) {
+    PROGRESS.with(|progress| {
+        let mut progress = progress.borrow_mut();
+        progress.phase = phase;
+        progress.span = None;
+    });
+}
+
+/// Records the span of the code that the compiler is working on
+pub(crate) fn record_span(span: &Span) {
+    PROGRESS.with(|progress| progress.borrow_mut().span = Some(span.clone()));
+}
+
+/// The error for a program that uses a feature the compiler can't handle
+/// yet, such as `"Declaring functions inside other functions"`, which
+/// points at the last recorded span
+pub(crate) fn not_yet_supported(feature: &str) -> RoxError {
+    let error = RoxError::with_file_placeholder(&format!(
+        "{} is not yet supported",
+        feature
+    ));
+    match PROGRESS.with(|progress| progress.borrow().span.clone()) {
+        Some(span) => error.with_label("not yet supported", span),
+        None => error,
+    }
+}
+
+/// Runs the compiler, reporting a panic as an internal compiler error
+pub(crate) fn catch_panics<T>(
+    compile: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    install_panic_hook();
+    let was_catching = PROGRESS.with(|progress| {
+        std::mem::replace(&mut progress.borrow_mut().catching, true)
+    });
+    let result = panic::catch_unwind(AssertUnwindSafe(compile));
+    let (phase, span, recorded_panic) = PROGRESS.with(|progress| {
+        let mut progress = progress.borrow_mut();
+        progress.catching = was_catching;
+        (progress.phase, progress.span.take(), progress.panic.take())
+    });
+    match result {
+        Ok(result) => result,
+        Err(payload) => Err(internal_error(
+            phase,
+            span,
+            recorded_panic.unwrap_or_else(|| Panic {
+                message: payload_message(payload.as_ref()),
+                location: "an unknown location".to_string(),
+                backtrace: Backtrace::disabled(),
+            }),
+        )),
+    }
+}
+
+/// Installs a panic hook that records panics caught by `catch_panics`
+/// instead of printing them, and leaves every other panic to the
+/// previous hook
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let recorded = PROGRESS.with(|progress| {
+                let mut progress = match progress.try_borrow_mut() {
+                    Ok(progress) if progress.catching => progress,
+                    _ => return false,
+                };
+                progress.panic = Some(Panic {
+                    message: payload_message(info.payload()),
+                    location: info.location().map_or_else(
+                        || "an unknown location".to_string(),
+                        ToString::to_string,
+                    ),
+                    backtrace: Backtrace::force_capture(),
+                });
+                true
+            });
+            if !recorded {
+                previous_hook(info);
+            }
+        }));
+    });
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+fn internal_error(phase: Phase, span: Option<Span>, panic: Panic) -> Error {
+    let mut error = RoxError::with_file_placeholder("internal compiler error");
+    if let Some(span) = span {
+        error = error.with_label(
+            &format!("the compiler panicked during {} here", phase),
+            span,
+        );
+    }
+    Error::Internal(
+        error
+            .with_note(&format!(
+                "panicked during {} at {}: {}",
+                phase, panic.location, panic.message
+            ))
+            .with_note("this is a bug in the Rox compiler, please report it at https://github.com/reese/rox/issues")
+            .with_note(&format!("backtrace:\n{}", panic.backtrace)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_panics_as_internal_compiler_errors() {
+        let result: Result<(), Error> = catch_panics(|| {
+            enter_phase(Phase::TypeChecking);
+            record_span(&Span(3, 7));
+            panic!("Variable not defined");
+        });
+
+        match result {
+            Err(Error::Internal(error)) => {
+                assert_eq!(
+                    error.message.as_deref(),
+                    Some("internal compiler error")
+                );
+                assert_eq!(error.labels[0].range, 3..7);
+                assert!(error.notes[0]
+                    .starts_with("panicked during type checking at "));
+                assert!(error.notes[0].ends_with(": Variable not defined"));
+            }
+            result => {
+                panic!("Expected an internal compiler error, got {:?}", result)
+            }
+        }
+    }
+
+    #[test]
+    fn it_reports_unsupported_features_as_errors() {
+        let result: Result<(), Error> = catch_panics(|| {
+            enter_phase(Phase::CodeGeneration);
+            record_span(&Span(1, 2));
+            Err(Error::Codegen(not_yet_supported(
+                "Declaring functions inside other functions",
+            )))
+        });
+
+        match result {
+            Err(Error::Codegen(error)) => {
+                assert_eq!(
+                    error.message.as_deref(),
+                    Some("Declaring functions inside other functions is not yet supported")
+                );
+                assert_eq!(error.labels[0].range, 1..2);
+            }
+            result => panic!("Expected a codegen error, got {:?}", result),
+        }
+    }
+}
@@ -3,15 +3,20 @@ pub(crate) use builtins::*;
 pub use compile::*;
 pub use errors::*;
 pub use function_translator::*;
+pub(crate) use ice::*;
 pub(crate) use parser::*;
 pub(crate) use semant::*;
 pub use stack::*;
+pub use target::*;
 
 mod builtins;
 mod compile;
 mod compiler_state;
 mod errors;
 mod function_translator;
+mod ice;
 mod parser;
+mod runtime;
 mod semant;
 mod stack;
+mod target;
@@ -0,0 +1,811 @@
+//! # Lexer
+//! The lexer turns Rox source code into the `Token`s consumed by the
+//! LALRPOP grammar in `rox_parser.lalrpop`.
+//!
+//! Lexical errors, such as stray characters or integer literals that don't
+//! fit in a `UInt64`, don't stop the lexer. They're collected instead, so
+//! that they can be reported along with any errors found by the parser.
+//!
+//! ## Numbers
+//! Integers can be written in decimal, hexadecimal (`0xFF`), binary
+//! (`0b1010`) or octal (`0o755`), and floats can have an exponent
+//! (`1.5e-3`). Underscores can be used to group digits (`1_000_000`), and
+//! a suffix such as `i64` or `f32` sets the type of the literal. The lexer
+//! only reports integers that don't fit in a `UInt64`. Whether an integer
+//! fits in its type is checked by the type checker, since an unsuffixed
+//! literal can have any integer type and `-128i8` is lexed as `-`
+//! followed by `128i8`. Floats are checked against their suffix here.
+//!
+//! ## Comments
+//! Rox supports `//` line comments, `/* */` block comments (which can be
+//! nested) and `///` doc comments. Doc comments are kept as tokens so the
+//! grammar can attach them to the declaration that follows them.
+use super::string_literal::parse_escape;
+use super::{NumericSuffix, Span, Spanned};
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token<'input> {
+    // Keywords
+    And,
+    As,
+    Const,
+    Do,
+    Else,
+    End,
+    Extern,
+    False,
+    Fn,
+    If,
+    Let,
+    Mut,
+    Or,
+    Return,
+    Static,
+    Struct,
+    True,
+
+    // Punctuation
+    Ampersand,
+    Arrow,
+    Bang,
+    Caret,
+    Colon,
+    Comma,
+    Dot,
+    DoubleColon,
+    DoubleEquals,
+    Equals,
+    GreaterThan,
+    /// A `>` directly followed by another `>`. The grammar uses this for
+    /// `>>`, which can't be a single token since it also closes nested
+    /// generic arguments, as in `List<List<Int>>`.
+    JoinedGreaterThan,
+    LeftBrace,
+    LeftBracket,
+    LeftParen,
+    LessThan,
+    Minus,
+    NotEquals,
+    Pipe,
+    Plus,
+    RightBrace,
+    RightBracket,
+    RightParen,
+    Semicolon,
+    ShiftLeft,
+    Slash,
+    Star,
+    Tilde,
+
+    // Literals
+    Char(char),
+    DocComment(&'input str),
+    Float(f64, Option<NumericSuffix>),
+    Identifier(&'input str),
+    Int(u64, Option<NumericSuffix>),
+    /// The raw string literal, including its quotes. Escapes and
+    /// interpolations are handled by the grammar (see `parse_string_literal`).
+    String(&'input str),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LexicalError {
+    UnexpectedCharacter(char),
+    /// A numeric literal that doesn't fit in the named type
+    NumberOutOfRange(&'static str),
+    InvalidNumber(&'static str),
+    UnterminatedString,
+    /// Errors in a character literal, including invalid escapes
+    InvalidCharacter(&'static str),
+    UnterminatedBlockComment,
+    /// Errors in the contents of a string literal, such as invalid escapes
+    InvalidStringLiteral(&'static str),
+}
+
+impl fmt::Display for LexicalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexicalError::UnexpectedCharacter(character) => {
+                write!(f, "Unexpected character `{}`", character)
+            }
+            LexicalError::NumberOutOfRange(type_name) => {
+                write!(f, "Literal is out of range for `{}`", type_name)
+            }
+            LexicalError::InvalidNumber(message) => write!(f, "{}", message),
+            LexicalError::UnterminatedString => {
+                write!(f, "Unterminated string literal")
+            }
+            LexicalError::InvalidCharacter(message) => write!(f, "{}", message),
+            LexicalError::UnterminatedBlockComment => {
+                write!(f, "Unterminated block comment")
+            }
+            LexicalError::InvalidStringLiteral(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+/// The item type LALRPOP expects from an external lexer
+pub type LexerItem<'input> =
+    std::result::Result<(usize, Token<'input>, usize), LexicalError>;
+
+pub struct Lexer<'input> {
+    source: &'input str,
+    chars: Peekable<CharIndices<'input>>,
+    /// Added to every position, for lexing a slice of a larger file
+    offset: usize,
+    errors: Vec<Spanned<LexicalError>>,
+    /// Whether the previous token was a `.`, in which case a number is a
+    /// tuple index, so that `pair.0.1` isn't lexed with the float `0.1`
+    after_dot: bool,
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(source: &'input str) -> Self {
+        Lexer::with_offset(source, 0)
+    }
+
+    /// Creates a lexer for source code that starts at `offset` in its file
+    pub fn with_offset(source: &'input str, offset: usize) -> Self {
+        Lexer {
+            source,
+            chars: source.char_indices().peekable(),
+            offset,
+            errors: Vec::new(),
+            after_dot: false,
+        }
+    }
+
+    /// The errors encountered so far
+    pub fn errors(&self) -> &[Spanned<LexicalError>] {
+        &self.errors
+    }
+
+    fn error(&mut self, error: LexicalError, start: usize, end: usize) {
+        self.errors.push(Spanned {
+            value: error,
+            span: Span(self.offset + start, self.offset + end),
+        });
+    }
+
+    fn token(
+        &self,
+        start: usize,
+        token: Token<'input>,
+        end: usize,
+    ) -> Option<LexerItem<'input>> {
+        Some(Ok((self.offset + start, token, self.offset + end)))
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, character)| *character)
+    }
+
+    /// The index just past the last consumed character
+    fn position(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.source.len(), |(index, _)| *index)
+    }
+
+    /// Consumes the next character if it matches `expected`
+    fn next_is(&mut self, expected: char) -> bool {
+        if self.peek_char() == Some(expected) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_while(&mut self, predicate: impl Fn(char) -> bool) -> usize {
+        while let Some(character) = self.peek_char() {
+            if !predicate(character) {
+                break;
+            }
+            self.chars.next();
+        }
+        self.position()
+    }
+
+    fn identifier_or_keyword(&mut self, start: usize) -> Token<'input> {
+        let mut end =
+            self.consume_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        // Identifiers may end with a `?` or `!`, as in `is_valid?`,
+        // as long as it isn't part of a `!=`
+        if let Some(suffix @ '?') | Some(suffix @ '!') = self.peek_char() {
+            let is_not_equals =
+                suffix == '!' && self.source[end + 1..].starts_with('=');
+            if !is_not_equals {
+                self.chars.next();
+                end += 1;
+            }
+        }
+        match &self.source[start..end] {
+            "and" => Token::And,
+            "as" => Token::As,
+            "const" => Token::Const,
+            "do" => Token::Do,
+            "else" => Token::Else,
+            "end" => Token::End,
+            "extern" => Token::Extern,
+            "false" => Token::False,
+            "fn" => Token::Fn,
+            "if" => Token::If,
+            "let" => Token::Let,
+            "mut" => Token::Mut,
+            "or" => Token::Or,
+            "return" => Token::Return,
+            "static" => Token::Static,
+            "struct" => Token::Struct,
+            "true" => Token::True,
+            identifier => Token::Identifier(identifier),
+        }
+    }
+
+    /// The character after the next one, without consuming anything
+    fn peek_second_char(&mut self) -> Option<char> {
+        let position = self.position();
+        self.source[position..].chars().nth(1)
+    }
+
+    /// Lexes the digits of a tuple index, as in `pair.0`
+    fn tuple_index(&mut self, start: usize) -> Token<'input> {
+        let end = self.consume_while(|c| c.is_ascii_digit());
+        match self.source[start..end].parse() {
+            Ok(index) => Token::Int(index, None),
+            Err(_) => {
+                self.error(LexicalError::NumberOutOfRange("Int"), start, end);
+                Token::Int(0, None)
+            }
+        }
+    }
+
+    fn number(&mut self, start: usize, first: char) -> Token<'input> {
+        let radix = match (first, self.peek_char()) {
+            ('0', Some('x')) => 16,
+            ('0', Some('b')) => 2,
+            ('0', Some('o')) => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.chars.next();
+            return self.radix_integer(start, radix);
+        }
+
+        let mut is_float = false;
+        let mut end = self.consume_while(|c| c.is_ascii_digit() || c == '_');
+        // A `.` is only part of the number if a digit follows it
+        if self.peek_char() == Some('.')
+            && self
+                .peek_second_char()
+                .map_or(false, |c| c.is_ascii_digit())
+        {
+            self.chars.next();
+            end = self.consume_while(|c| c.is_ascii_digit() || c == '_');
+            is_float = true;
+        }
+        if let Some('e') | Some('E') = self.peek_char() {
+            let exponent = &self.source[end + 1..];
+            let sign_length = match exponent.chars().next() {
+                Some('+') | Some('-') => 1,
+                _ => 0,
+            };
+            let has_digits = exponent[sign_length..]
+                .chars()
+                .next()
+                .map_or(false, |c| c.is_ascii_digit());
+            if has_digits {
+                self.chars.next();
+                if sign_length == 1 {
+                    self.chars.next();
+                }
+                end = self.consume_while(|c| c.is_ascii_digit() || c == '_');
+                is_float = true;
+            }
+        }
+
+        let digits = self.source[start..end].replace('_', "");
+        let suffix = self.numeric_suffix(end);
+        match suffix {
+            Some(suffix) if is_float && !suffix.is_float() => {
+                let suffix_end = self.position();
+                self.error(
+                    LexicalError::InvalidNumber(
+                        "Integer suffixes can't be used on float literals",
+                    ),
+                    end,
+                    suffix_end,
+                );
+                self.float(digits, None, start, end)
+            }
+            Some(suffix) if is_float || suffix.is_float() => {
+                self.float(digits, Some(suffix), start, end)
+            }
+            None if is_float => self.float(digits, None, start, end),
+            _ => self.integer(&digits, 10, suffix, start, end),
+        }
+    }
+
+    /// Lexes the digits of a hexadecimal, binary or octal integer
+    /// after its `0x`, `0b` or `0o` prefix
+    fn radix_integer(&mut self, start: usize, radix: u32) -> Token<'input> {
+        let digits_start = self.position();
+        let end = self.consume_while(|c| c.is_ascii_hexdigit() || c == '_');
+        let digits = self.source[digits_start..end].replace('_', "");
+        // Hexadecimal digits are consumed for every radix so that a literal
+        // like `0b102` is reported as a single invalid digit
+        if let Some((index, _)) = self.source[digits_start..end]
+            .char_indices()
+            .find(|(_, c)| *c != '_' && !c.is_digit(radix))
+        {
+            let position = digits_start + index;
+            self.error(
+                LexicalError::InvalidNumber(match radix {
+                    2 => "Invalid digit for a binary literal",
+                    8 => "Invalid digit for an octal literal",
+                    _ => "Invalid digit for a hexadecimal literal",
+                }),
+                position,
+                position + 1,
+            );
+            self.numeric_suffix(end);
+            return Token::Int(0, None);
+        }
+        if digits.is_empty() {
+            self.error(
+                LexicalError::InvalidNumber("Expected digits after the prefix"),
+                start,
+                end,
+            );
+            return Token::Int(0, None);
+        }
+        let suffix = match self.numeric_suffix(end) {
+            Some(suffix) if suffix.is_float() => {
+                let suffix_end = self.position();
+                self.error(
+                    LexicalError::InvalidNumber(
+                        "Float suffixes can only be used on decimal literals",
+                    ),
+                    end,
+                    suffix_end,
+                );
+                None
+            }
+            suffix => suffix,
+        };
+        self.integer(&digits, radix, suffix, start, end)
+    }
+
+    /// Lexes the suffix of a numeric literal that ends at `start`,
+    /// reporting any suffix that isn't a numeric type
+    fn numeric_suffix(&mut self, start: usize) -> Option<NumericSuffix> {
+        let end = self.consume_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        if start == end {
+            return None;
+        }
+        let suffix = NumericSuffix::from_suffix(&self.source[start..end]);
+        if suffix.is_none() {
+            self.error(
+                LexicalError::InvalidNumber(
+                    "Invalid suffix for number literal",
+                ),
+                start,
+                end,
+            );
+        }
+        suffix
+    }
+
+    fn integer(
+        &mut self,
+        digits: &str,
+        radix: u32,
+        suffix: Option<NumericSuffix>,
+        start: usize,
+        end: usize,
+    ) -> Token<'input> {
+        match u64::from_str_radix(digits, radix) {
+            Ok(int) => Token::Int(int, suffix),
+            Err(_) => {
+                let literal_type = suffix.unwrap_or(NumericSuffix::U64);
+                self.error(
+                    LexicalError::NumberOutOfRange(literal_type.type_name()),
+                    start,
+                    end,
+                );
+                Token::Int(0, suffix)
+            }
+        }
+    }
+
+    fn float(
+        &mut self,
+        digits: String,
+        suffix: Option<NumericSuffix>,
+        start: usize,
+        end: usize,
+    ) -> Token<'input> {
+        let float: f64 = digits.parse().unwrap_or_else(|_| {
+            panic!("Float literal {} should always be valid", digits)
+        });
+        let is_in_range = match suffix {
+            Some(NumericSuffix::F32) => (float as f32).is_finite(),
+            _ => float.is_finite(),
+        };
+        if is_in_range {
+            Token::Float(float, suffix)
+        } else {
+            let literal_type = suffix.unwrap_or(NumericSuffix::F64);
+            self.error(
+                LexicalError::NumberOutOfRange(literal_type.type_name()),
+                start,
+                end,
+            );
+            Token::Float(0.0, suffix)
+        }
+    }
+
+    /// Lexes a string literal, returning `None` if it's unterminated
+    fn string(&mut self, start: usize) -> Option<Token<'input>> {
+        while let Some((index, character)) = self.chars.next() {
+            match character {
+                '\\' => {
+                    self.chars.next();
+                }
+                '"' => return Some(Token::String(&self.source[start..=index])),
+                _ => {}
+            }
+        }
+        self.error(LexicalError::UnterminatedString, start, start + 1);
+        None
+    }
+
+    /// Lexes a character literal such as `'a'` or `'\n'`
+    fn character(&mut self, start: usize) -> Token<'input> {
+        let character = match self.chars.next() {
+            Some((index, '\\')) => match parse_escape(&mut self.chars, index) {
+                Ok(escaped) => escaped,
+                Err(error) => {
+                    self.error(
+                        LexicalError::InvalidCharacter(error.value),
+                        error.span.0,
+                        error.span.1,
+                    );
+                    '\0'
+                }
+            },
+            Some((_, '\'')) => {
+                self.error(
+                    LexicalError::InvalidCharacter("Empty character literal"),
+                    start,
+                    start + 2,
+                );
+                return Token::Char('\0');
+            }
+            Some((_, character)) if character != '\n' => character,
+            _ => '\0',
+        };
+        if !self.next_is('\'') {
+            // Skip the rest of the literal so it isn't lexed as code
+            self.consume_while(|c| c != '\'' && c != '\n');
+            let is_terminated = self.next_is('\'');
+            let end = self.position();
+            self.error(
+                LexicalError::InvalidCharacter(if is_terminated {
+                    "Character literals can only contain one character"
+                } else {
+                    "Expected `'` to end the character literal"
+                }),
+                start,
+                end,
+            );
+        }
+        Token::Char(character)
+    }
+
+    /// Skips a (possibly nested) block comment whose `/*` was consumed,
+    /// returning `false` if the comment is unterminated
+    fn block_comment(&mut self, start: usize) -> bool {
+        let mut depth = 1;
+        while let Some((_, character)) = self.chars.next() {
+            match character {
+                '/' if self.next_is('*') => depth += 1,
+                '*' if self.next_is('/') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.error(LexicalError::UnterminatedBlockComment, start, start + 2);
+        false
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = LexerItem<'input>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let after_dot = std::mem::take(&mut self.after_dot);
+        loop {
+            let (start, character) = self.chars.next()?;
+            let token = match character {
+                c if c.is_whitespace() => continue,
+                '/' if self.next_is('/') => {
+                    let end = self.consume_while(|c| c != '\n');
+                    let comment = &self.source[start..end];
+                    // `////` (or more slashes) is a regular comment
+                    if comment.starts_with("///")
+                        && !comment.starts_with("////")
+                    {
+                        return self.token(
+                            start,
+                            Token::DocComment(comment),
+                            end,
+                        );
+                    }
+                    continue;
+                }
+                '/' if self.next_is('*') => {
+                    if self.block_comment(start) {
+                        continue;
+                    }
+                    return None;
+                }
+                '\'' => self.character(start),
+                '"' => match self.string(start) {
+                    Some(token) => token,
+                    None => return None,
+                },
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    self.identifier_or_keyword(start)
+                }
+                c if c.is_ascii_digit() && after_dot => self.tuple_index(start),
+                c if c.is_ascii_digit() => self.number(start, c),
+                '-' if self.next_is('>') => Token::Arrow,
+                ':' if self.next_is(':') => Token::DoubleColon,
+                '=' if self.next_is('=') => Token::DoubleEquals,
+                '!' if self.next_is('=') => Token::NotEquals,
+                '<' if self.next_is('<') => Token::ShiftLeft,
+                '>' if self.peek_char() == Some('>') => {
+                    Token::JoinedGreaterThan
+                }
+                '&' => Token::Ampersand,
+                '!' => Token::Bang,
+                '^' => Token::Caret,
+                ':' => Token::Colon,
+                ',' => Token::Comma,
+                '.' => {
+                    self.after_dot = true;
+                    Token::Dot
+                }
+                '=' => Token::Equals,
+                '>' => Token::GreaterThan,
+                '{' => Token::LeftBrace,
+                '[' => Token::LeftBracket,
+                '(' => Token::LeftParen,
+                '<' => Token::LessThan,
+                '-' => Token::Minus,
+                '|' => Token::Pipe,
+                '+' => Token::Plus,
+                '}' => Token::RightBrace,
+                ']' => Token::RightBracket,
+                ')' => Token::RightParen,
+                ';' => Token::Semicolon,
+                '/' => Token::Slash,
+                '*' => Token::Star,
+                '~' => Token::Tilde,
+                unexpected => {
+                    self.error(
+                        LexicalError::UnexpectedCharacter(unexpected),
+                        start,
+                        start + unexpected.len_utf8(),
+                    );
+                    continue;
+                }
+            };
+            let end = self.position();
+            return self.token(start, token, end);
+        }
+    }
+}
+
+/// Gets the text of a `///` doc comment token without its slashes
+pub(crate) fn doc_comment_text(comment: &str) -> String {
+    let text = &comment[3..];
+    text.strip_prefix(' ')
+        .unwrap_or(text)
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token<'_>> {
+        Lexer::new(source)
+            .map(|item| item.map(|(_, token, _)| token).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_does_not_include_dashes_in_identifiers() {
+        assert_eq!(
+            tokens("a-b"),
+            vec![Token::Identifier("a"), Token::Minus, Token::Identifier("b")]
+        );
+    }
+
+    #[test]
+    fn it_allows_question_marks_and_bangs_at_the_end_of_identifiers() {
+        assert_eq!(
+            tokens("is_real? a!=b"),
+            vec![
+                Token::Identifier("is_real?"),
+                Token::Identifier("a"),
+                Token::NotEquals,
+                Token::Identifier("b")
+            ]
+        );
+    }
+
+    #[test]
+    fn it_splits_double_greater_thans() {
+        assert_eq!(
+            tokens("a >> 1 << 2"),
+            vec![
+                Token::Identifier("a"),
+                Token::JoinedGreaterThan,
+                Token::GreaterThan,
+                Token::Int(1, None),
+                Token::ShiftLeft,
+                Token::Int(2, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_lexes_nested_tuple_indices_as_integers() {
+        assert_eq!(
+            tokens("pair.0.1 1.5"),
+            vec![
+                Token::Identifier("pair"),
+                Token::Dot,
+                Token::Int(0, None),
+                Token::Dot,
+                Token::Int(1, None),
+                Token::Float(1.5, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_out_of_range_integers() {
+        let mut lexer = Lexer::new("let x = 99999999999999999999;");
+        let lexed_tokens = (&mut lexer).collect::<Vec<_>>();
+
+        assert_eq!(lexed_tokens.len(), 5);
+        assert_eq!(lexer.errors().len(), 1);
+        assert_eq!(
+            lexer.errors()[0].value,
+            LexicalError::NumberOutOfRange("UInt64")
+        );
+        assert_eq!(
+            (lexer.errors()[0].span.0, lexer.errors()[0].span.1),
+            (8, 28)
+        );
+    }
+
+    #[test]
+    fn it_reports_stray_characters_and_continues() {
+        let mut lexer = Lexer::new("let $x = 1;");
+        let lexed_tokens = (&mut lexer).collect::<Vec<_>>();
+
+        assert_eq!(lexed_tokens.len(), 5);
+        assert_eq!(
+            lexer.errors()[0].value,
+            LexicalError::UnexpectedCharacter('$')
+        );
+        assert_eq!(
+            (lexer.errors()[0].span.0, lexer.errors()[0].span.1),
+            (4, 5)
+        );
+    }
+
+    #[test]
+    fn it_lexes_integer_literal_forms() {
+        assert_eq!(
+            tokens("0xFF 0b1010 0o755 1_000_000 10i64 0xFFu8"),
+            vec![
+                Token::Int(255, None),
+                Token::Int(10, None),
+                Token::Int(493, None),
+                Token::Int(1_000_000, None),
+                Token::Int(10, Some(NumericSuffix::I64)),
+                Token::Int(255, Some(NumericSuffix::U8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_lexes_float_literal_forms() {
+        assert_eq!(
+            tokens("1.5e-3 2E10 1_000.5 3f32 1.0"),
+            vec![
+                Token::Float(1.5e-3, None),
+                Token::Float(2e10, None),
+                Token::Float(1000.5, None),
+                Token::Float(3.0, Some(NumericSuffix::F32)),
+                Token::Float(1.0, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_checks_literals_against_their_suffix() {
+        let mut lexer = Lexer::new("255u8 99999999999999999999u8 3e39f32");
+        let lexed_tokens = (&mut lexer).collect::<Vec<_>>();
+
+        assert_eq!(lexed_tokens.len(), 3);
+        let errors = lexer
+            .errors()
+            .iter()
+            .map(|error| (error.value.clone(), error.span.0, error.span.1))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (LexicalError::NumberOutOfRange("UInt8"), 6, 26),
+                (LexicalError::NumberOutOfRange("Float32"), 29, 33),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_invalid_digits_and_suffixes() {
+        let mut lexer = Lexer::new("0b102 12abc");
+        (&mut lexer).for_each(drop);
+
+        let spans = lexer
+            .errors()
+            .iter()
+            .map(|error| (error.span.0, error.span.1))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![(4, 5), (8, 11)]);
+    }
+
+    #[test]
+    fn it_lexes_character_literals() {
+        let mut lexer = Lexer::new(r"'a' '\n' '\u{1F600}' 'ab'");
+        let lexed_tokens = (&mut lexer)
+            .map(|item| item.map(|(_, token, _)| token).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            &lexed_tokens[..3],
+            &[Token::Char('a'), Token::Char('\n'), Token::Char('😀')]
+        );
+        assert_eq!(lexer.errors().len(), 1);
+        assert_eq!(
+            (lexer.errors()[0].span.0, lexer.errors()[0].span.1),
+            (21, 25)
+        );
+    }
+
+    #[test]
+    fn it_offsets_token_positions() {
+        let lexed_tokens = Lexer::with_offset("name", 10)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        assert_eq!(lexed_tokens, vec![(10, Token::Identifier("name"), 14)]);
+    }
+}
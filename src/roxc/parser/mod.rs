@@ -1,35 +1,93 @@
+pub(crate) mod lexer;
+mod string_literal;
 mod syntax;
 
-use crate::roxc::RoxError;
-use crate::Result;
-use std::path::PathBuf;
-use std::{fs::read_to_string, path::Path};
+use crate::roxc::{Result, RoxError};
+use lalrpop_util::{ErrorRecovery, ParseError};
+pub(crate) use lexer::doc_comment_text;
+use lexer::{Lexer, LexicalError, Token};
+use std::path::Path;
+pub(crate) use string_literal::parse_string_literal;
 pub use syntax::*;
 
 lalrpop_mod!(#[allow(clippy::all)] pub rox_parser);
 
+/// Errors that the parser recovered from
+pub(crate) type Errors<'input> =
+    Vec<Spanned<ErrorRecovery<usize, Token<'input>, LexicalError>>>;
+
 pub fn parse_string(source: &str, path: &Path) -> Result<Vec<Box<Statement>>> {
     let mut errors = Vec::new();
-    let declarations = rox_parser::ProgramParser::new()
-        .parse(&mut errors, source)
-        .unwrap();
+    let mut lexer = Lexer::new(source);
+    // An unrecoverable error (such as reaching the end of the file inside
+    // of an unterminated block comment) is reported with the others
+    let declarations =
+        match rox_parser::ProgramParser::new().parse(&mut errors, &mut lexer) {
+            Ok(declarations) => declarations,
+            Err(error) => {
+                let span = parse_error_span(&error);
+                errors.push(Spanned {
+                    value: ErrorRecovery {
+                        error,
+                        dropped_tokens: Vec::new(),
+                    },
+                    span,
+                });
+                Vec::new()
+            }
+        };
+    lexer
+        .errors()
+        .iter()
+        .cloned()
+        .for_each(|error| push_error(&mut errors, error));
+    errors.sort_by_key(|error| error.span.0);
     match errors {
         empty_vec if empty_vec.is_empty() => Ok(declarations),
-        error_vec => Err(RoxError::from_error_recoveries(error_vec, path)),
+        error_vec => {
+            Err(RoxError::from_error_recoveries(error_vec, path, source))
+        }
+    }
+}
+
+fn parse_error_span(
+    error: &ParseError<usize, Token<'_>, LexicalError>,
+) -> Span {
+    match error {
+        ParseError::InvalidToken { location }
+        | ParseError::UnrecognizedEOF { location, .. } => {
+            Span(*location, *location)
+        }
+        ParseError::UnrecognizedToken {
+            token: (start, _, end),
+            ..
+        }
+        | ParseError::ExtraToken {
+            token: (start, _, end),
+        } => Span(*start, *end),
+        ParseError::User { .. } => Span(0, 0),
     }
 }
 
-pub(crate) fn parse_file(
-    file: impl Into<PathBuf> + Clone,
-) -> Result<Vec<Box<Statement>>> {
-    let path_buf: &PathBuf = &file.into();
-    let source = read_to_string(path_buf).unwrap();
-    parse_string(&source, path_buf)
+/// Records an error found outside of the grammar itself,
+/// such as an invalid escape sequence in a string literal
+pub(crate) fn push_error(
+    errors: &mut Errors<'_>,
+    error: Spanned<LexicalError>,
+) {
+    errors.push(Spanned {
+        value: ErrorRecovery {
+            error: ParseError::User { error: error.value },
+            dropped_tokens: Vec::new(),
+        },
+        span: error.span,
+    });
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::PathBuf;
 
     #[test]
     fn it_parses_functions_with_no_arguments() {
@@ -78,4 +136,263 @@ mod tests {
 
         assert!(parse_string(source, &PathBuf::new()).is_ok());
     }
+
+    #[test]
+    fn it_parses_nested_generic_types() {
+        let source = r#"
+        fn flatten(lists: List<List<Int>>) -> List<Int> do
+            return lists >> 1;
+        end
+        "#;
+
+        assert!(parse_string(source, &PathBuf::new()).is_ok());
+    }
+
+    fn parse_main_body(body: &str) -> (Vec<Box<Statement>>, Vec<Span>) {
+        let source = format!("fn main() do\n{}\nend", body);
+        let mut errors = Vec::new();
+        let mut lexer = Lexer::new(&source);
+        let declarations = rox_parser::ProgramParser::new()
+            .parse(&mut errors, &mut lexer)
+            .unwrap_or_default();
+        let error_spans = errors
+            .into_iter()
+            .map(|error| error.span)
+            .chain(lexer.errors().iter().map(|error| error.span.clone()))
+            .collect();
+        (declarations, error_spans)
+    }
+
+    fn first_expression(declarations: &[Box<Statement>]) -> Expression {
+        match declarations[0].as_ref() {
+            Statement::FunctionDeclaration(.., body) => {
+                match body[0].as_ref() {
+                    Statement::Expression(expression) => {
+                        expression.as_ref().clone()
+                    }
+                    statement => {
+                        panic!("Expected expression, got {:?}", statement)
+                    }
+                }
+            }
+            statement => panic!("Expected function, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn it_parses_casts_before_multiplication() {
+        let (declarations, errors) = parse_main_body("count as Float * 2.0;");
+
+        assert!(errors.is_empty());
+        match first_expression(&declarations) {
+            Expression::Operation(left, _, _) => match left.as_ref() {
+                Expression::Cast(_, type_name) => {
+                    assert_eq!(type_name.value, "Float")
+                }
+                expression => panic!("Expected cast, got {:?}", expression),
+            },
+            expression => panic!("Expected operation, got {:?}", expression),
+        }
+    }
+
+    #[test]
+    fn it_parses_bitwise_operators_with_precedence() {
+        let (declarations, errors) = parse_main_body("a | b & c << 1;");
+
+        assert!(errors.is_empty());
+        let operation = |expression: &Expression| match expression {
+            Expression::Operation(_, operation, right) => {
+                (operation.value.clone(), right.as_ref().clone())
+            }
+            expression => panic!("Expected operation, got {:?}", expression),
+        };
+        let (or, and) = operation(&first_expression(&declarations));
+        let (and, shift) = operation(&and);
+        let (shift, _) = operation(&shift);
+        assert!(matches!(
+            (or, and, shift),
+            (
+                Operation::BitwiseOr,
+                Operation::BitwiseAnd,
+                Operation::ShiftLeft
+            )
+        ));
+    }
+
+    #[test]
+    fn it_parses_tuple_destructuring_and_nested_access() {
+        let (declarations, errors) =
+            parse_main_body("let (a, (b, c)) = (1, (pair.0.1, 2.5));");
+
+        assert!(errors.is_empty());
+        match first_expression(&declarations) {
+            Expression::Variable(Pattern::Tuple(patterns), None, value) => {
+                assert!(matches!(
+                    patterns.as_slice(),
+                    [Pattern::Identifier(..), Pattern::Tuple(inner)]
+                        if inner.len() == 2
+                ));
+                match value.as_ref() {
+                    Expression::Tuple(elements) => match elements[1].as_ref() {
+                        Expression::Tuple(inner) => assert!(matches!(
+                            inner[0].as_ref(),
+                            Expression::TupleAccess(tuple, index)
+                                if index.value == 1 && matches!(
+                                    tuple.as_ref(),
+                                    Expression::TupleAccess(_, index) if index.value == 0
+                                )
+                        )),
+                        expression => {
+                            panic!("Expected tuple, got {:?}", expression)
+                        }
+                    },
+                    expression => {
+                        panic!("Expected tuple, got {:?}", expression)
+                    }
+                }
+            }
+            expression => panic!("Expected variable, got {:?}", expression),
+        }
+    }
+
+    #[test]
+    fn it_parses_tuple_and_function_types() {
+        let source = "fn pair(f: (Int) -> Int) -> (Int, (String,)) do end";
+        let mut errors = Vec::new();
+        let declarations = rox_parser::ProgramParser::new()
+            .parse(&mut errors, Lexer::new(source))
+            .unwrap();
+
+        assert!(errors.is_empty());
+        match declarations[0].as_ref() {
+            Statement::FunctionDeclaration(
+                ..,
+                params,
+                Some(return_type),
+                _,
+            ) => {
+                assert!(matches!(
+                    params[0].2.as_ref(),
+                    TypeName::Function(arguments, _)
+                        if matches!(arguments[0].as_ref(), TypeName::Type(_))
+                ));
+                match return_type.as_ref() {
+                    TypeName::Tuple(elements) => assert!(matches!(
+                        elements[1].as_ref(),
+                        TypeName::Tuple(inner) if inner.len() == 1
+                    )),
+                    type_name => panic!("Expected tuple, got {:?}", type_name),
+                }
+            }
+            statement => panic!("Expected function, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn it_parses_string_escapes() {
+        let (declarations, errors) =
+            parse_main_body(r#""tab\t \"quote\" \\ \u{1F980}\n";"#);
+
+        assert!(errors.is_empty());
+        match first_expression(&declarations) {
+            Expression::String(string) => {
+                assert_eq!(string.value, "tab\t \"quote\" \\ \u{1F980}\n")
+            }
+            expression => panic!("Expected string, got {:?}", expression),
+        }
+    }
+
+    #[test]
+    fn it_reports_invalid_escapes_at_the_escape() {
+        let body = r#""valid \q invalid";"#;
+        let (_, errors) = parse_main_body(body);
+        let escape_start = "fn main() do\n".len() + body.find('\\').unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, escape_start);
+        assert_eq!(errors[0].1, escape_start + 2);
+    }
+
+    #[test]
+    fn it_parses_string_interpolation() {
+        let (declarations, errors) =
+            parse_main_body(r#""Hello, {name}! You are {age + 1}";"#);
+
+        assert!(errors.is_empty());
+        match first_expression(&declarations) {
+            Expression::InterpolatedString(parts) => {
+                assert_eq!(parts.len(), 4);
+                assert!(matches!(
+                    parts[1].as_ref(),
+                    Expression::Identifier(name) if name.value == "name"
+                ));
+                assert!(matches!(parts[3].as_ref(), Expression::Operation(..)));
+            }
+            expression => {
+                panic!("Expected interpolation, got {:?}", expression)
+            }
+        }
+    }
+
+    #[test]
+    fn it_reports_unterminated_interpolation() {
+        let (_, errors) = parse_main_body(r#""Hello, {name";"#);
+
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn it_ignores_line_and_nested_block_comments() {
+        let (declarations, errors) = parse_main_body(
+            r#"
+            // A line comment with "quotes"
+            /* A block /* with a nested */ comment */
+            "not // a comment";
+            "#,
+        );
+
+        assert!(errors.is_empty());
+        assert!(matches!(
+            first_expression(&declarations),
+            Expression::String(string) if string.value == "not // a comment"
+        ));
+    }
+
+    #[test]
+    fn it_reports_unterminated_block_comments() {
+        let (_, errors) = parse_main_body("/* never /* closed */");
+        let comment_start = "fn main() do\n".len();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, comment_start);
+    }
+
+    #[test]
+    fn it_keeps_doc_comments_on_declarations() {
+        let source = r#"
+        /// Says hello
+        ///
+        /// Twice, actually
+        fn hello() do
+        end
+
+        //// Not a doc comment
+        struct Empty {}
+        "#;
+        let mut errors = Vec::new();
+        let declarations = rox_parser::ProgramParser::new()
+            .parse(&mut errors, Lexer::new(source))
+            .unwrap();
+
+        assert!(errors.is_empty());
+        assert!(matches!(
+            declarations[0].as_ref(),
+            Statement::FunctionDeclaration(docs, ..)
+                if docs == &["Says hello", "", "Twice, actually"]
+        ));
+        assert!(matches!(
+            declarations[1].as_ref(),
+            Statement::StructDeclaration(docs, ..) if docs.is_empty()
+        ));
+    }
 }
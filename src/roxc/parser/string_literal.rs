@@ -0,0 +1,256 @@
+//! # String Literals
+//! String literals are lexed as a single token, so their contents are
+//! handled here: escape sequences are resolved and any `{expression}`
+//! interpolations are parsed into their own expressions.
+//!
+//! N.B. Because the lexer ends a string at the first unescaped quote,
+//! interpolated expressions can't contain string literals themselves.
+use super::lexer::{Lexer, LexicalError};
+use super::rox_parser::ExpressionParser;
+use super::{push_error, Errors, Expression, Span, Spanned};
+
+enum Segment {
+    Text(Spanned<String>),
+    /// The source of an interpolated expression and its offset in the file
+    Interpolation(Spanned<String>),
+}
+
+/// Builds the expression for a string literal token, including its quotes.
+/// Errors are reported by pushing them onto `errors` so that parsing can
+/// continue with the rest of the file.
+pub(crate) fn parse_string_literal<'input>(
+    literal: &'input str,
+    span: Span,
+    errors: &mut Errors<'input>,
+) -> Box<Expression> {
+    let segments = split_segments(literal, span.0, errors);
+    match segments.as_slice() {
+        [] => Box::new(Expression::String(Spanned {
+            value: String::new(),
+            span,
+        })),
+        [Segment::Text(text)] => Box::new(Expression::String(Spanned {
+            value: text.value.clone(),
+            span,
+        })),
+        _ => Box::new(Expression::InterpolatedString(
+            segments
+                .into_iter()
+                .map(|segment| match segment {
+                    Segment::Text(text) => Box::new(Expression::String(text)),
+                    Segment::Interpolation(source) => {
+                        parse_interpolation(source, errors)
+                    }
+                })
+                .collect(),
+        )),
+    }
+}
+
+fn split_segments(
+    literal: &str,
+    offset: usize,
+    errors: &mut Errors<'_>,
+) -> Vec<Segment> {
+    // Skip the opening quote
+    let content_offset = offset + 1;
+    let content = &literal[1..literal.len() - 1];
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut text_start = content_offset;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((index, character)) = chars.next() {
+        let position = content_offset + index;
+        match character {
+            '\\' => match parse_escape(&mut chars, position) {
+                Ok(escaped) => text.push(escaped),
+                Err(error) => push_string_error(errors, error),
+            },
+            '{' => {
+                let mut source = String::new();
+                let mut is_terminated = false;
+                for (_, inner) in &mut chars {
+                    if inner == '}' {
+                        is_terminated = true;
+                        break;
+                    }
+                    source.push(inner);
+                }
+                let interpolation_end = position + source.len() + 2;
+                if !is_terminated {
+                    push_string_error(
+                        errors,
+                        Spanned {
+                            value: "Unterminated interpolation, expected `}`",
+                            span: Span(position, interpolation_end - 1),
+                        },
+                    );
+                } else if source.trim().is_empty() {
+                    push_string_error(
+                        errors,
+                        Spanned {
+                            value: "Expected an expression inside `{}`",
+                            span: Span(position, interpolation_end),
+                        },
+                    );
+                } else {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(Spanned {
+                            value: std::mem::take(&mut text),
+                            span: Span(text_start, position),
+                        }));
+                    }
+                    segments.push(Segment::Interpolation(Spanned {
+                        value: source,
+                        span: Span(position + 1, interpolation_end - 1),
+                    }));
+                }
+                text_start = interpolation_end;
+            }
+            '}' => push_string_error(
+                errors,
+                Spanned {
+                    value:
+                        "Unmatched `}`, use `\\}` to include a literal brace",
+                    span: Span(position, position + 1),
+                },
+            ),
+            _ => text.push(character),
+        }
+    }
+
+    if !text.is_empty() {
+        segments.push(Segment::Text(Spanned {
+            value: text,
+            span: Span(text_start, offset + literal.len() - 1),
+        }));
+    }
+    segments
+}
+
+/// Parses the escape sequence following a `\` at `position`.
+/// This is shared with character literals, which are handled by the lexer.
+pub(crate) fn parse_escape(
+    chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+    position: usize,
+) -> std::result::Result<char, Spanned<&'static str>> {
+    let (_, escaped) = chars.next().ok_or(Spanned {
+        value: "Expected an escape sequence after `\\`",
+        span: Span(position, position + 1),
+    })?;
+    let escape_span = Span(position, position + 1 + escaped.len_utf8());
+    match escaped {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '0' => Ok('\0'),
+        '\\' => Ok('\\'),
+        '"' => Ok('"'),
+        '\'' => Ok('\''),
+        '{' => Ok('{'),
+        '}' => Ok('}'),
+        'u' => parse_unicode_escape(chars, position),
+        _ => Err(Spanned {
+            value: "Unknown escape sequence",
+            span: escape_span,
+        }),
+    }
+}
+
+/// Parses the `{...}` part of a `\u{...}` escape
+fn parse_unicode_escape(
+    chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+    position: usize,
+) -> std::result::Result<char, Spanned<&'static str>> {
+    // `position` points at the backslash, so `\u` is two bytes long
+    if chars.peek().map(|(_, c)| *c) != Some('{') {
+        return Err(Spanned {
+            value: "Expected `{` after `\\u`",
+            span: Span(position, position + 2),
+        });
+    }
+    chars.next();
+    let mut digits = String::new();
+    let mut is_terminated = false;
+    for (_, character) in &mut *chars {
+        if character == '}' {
+            is_terminated = true;
+            break;
+        }
+        digits.push(character);
+    }
+    // `\u{` plus the digits and the closing brace
+    let escape_span = Span(
+        position,
+        position + 3 + digits.len() + if is_terminated { 1 } else { 0 },
+    );
+    if !is_terminated {
+        return Err(Spanned {
+            value: "Unterminated unicode escape, expected `}`",
+            span: escape_span,
+        });
+    }
+    if digits.is_empty() || digits.len() > 6 {
+        return Err(Spanned {
+            value: "Unicode escapes must have between 1 and 6 hex digits",
+            span: escape_span,
+        });
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(std::char::from_u32)
+        .ok_or(Spanned {
+            value: "Invalid unicode character in escape",
+            span: escape_span,
+        })
+}
+
+/// Parses the source of an interpolated expression.
+///
+/// The lexer is offset to the position of the expression in the file,
+/// so that the spans of the parsed expression line up with the original
+/// source.
+fn parse_interpolation(
+    source: Spanned<String>,
+    errors: &mut Errors<'_>,
+) -> Box<Expression> {
+    let mut lexer = Lexer::with_offset(&source.value, source.span.0);
+    let mut interpolation_errors = Vec::new();
+    let result =
+        ExpressionParser::new().parse(&mut interpolation_errors, &mut lexer);
+    lexer
+        .errors()
+        .iter()
+        .cloned()
+        .for_each(|error| push_error(errors, error));
+    let error_spans = interpolation_errors
+        .into_iter()
+        .map(|Spanned { span, .. }| span)
+        .chain(result.as_ref().err().map(|_| source.span.clone()))
+        .collect::<Vec<_>>();
+    if error_spans.is_empty() {
+        result.unwrap()
+    } else {
+        error_spans.into_iter().for_each(|span| {
+            push_string_error(
+                errors,
+                Spanned {
+                    value: "Invalid expression in string interpolation",
+                    span,
+                },
+            )
+        });
+        Box::new(Expression::ParseError)
+    }
+}
+
+fn push_string_error(errors: &mut Errors<'_>, error: Spanned<&'static str>) {
+    push_error(
+        errors,
+        Spanned {
+            value: LexicalError::InvalidStringLiteral(error.value),
+            span: error.span,
+        },
+    )
+}
@@ -26,41 +26,100 @@ pub struct LValue(pub Expression);
 pub enum Expression {
     BracketAccess(Box<Expression>, Box<Expression>),
     DotAccess(Box<Expression>, Identifier),
+    /// `tuple.0`
+    TupleAccess(Box<Expression>, Spanned<usize>),
     And(Box<Expression>, Box<Expression>),
     Array(Vec<Box<Expression>>),
     Assignment(Box<LValue>, Box<Expression>),
     Boolean(bool),
+    /// `expression as Type`
+    Cast(Box<Expression>, Spanned<Identifier>),
+    Char(Spanned<char>),
     FunctionCall(
         Spanned<Identifier>,
         Vec<Box<TypeName>>,
         Vec<Box<Expression>>,
     ),
     Identifier(Spanned<Identifier>),
-    Float(Spanned<f64>),
-    Int(Spanned<i32>),
+    /// A string literal with `{expression}` interpolations, where
+    /// the literal parts are `Expression::String`s
+    InterpolatedString(Vec<Box<Expression>>),
+    Float(Spanned<f64>, Option<NumericSuffix>),
+    Int(Spanned<u64>, Option<NumericSuffix>),
     Operation(Box<Expression>, Spanned<Operation>, Box<Expression>),
     Or(Box<Expression>, Box<Expression>),
+    /// `[value; length]`, where the length must be a constant
+    RepeatedArray(Box<Expression>, Spanned<Box<Expression>>),
     String(Spanned<String>),
     StructInstantiation(
         Spanned<Identifier>,
         Option<Vec<Box<TypeName>>>,
         Vec<(Identifier, Box<Expression>)>,
     ),
-    Unary(Unary, Box<Expression>),
-    Variable(Spanned<Identifier>, Box<Expression>),
+    Tuple(Vec<Box<Expression>>),
+    Unary(Spanned<Unary>, Box<Expression>),
+    /// `let pattern: Type = expression`, where the type annotation is optional
+    Variable(Pattern, Option<Spanned<Box<TypeName>>>, Box<Expression>),
     ParseError,
 }
 
+impl Expression {
+    /// The span of the token that identifies this expression, such as
+    /// the name of a called function or the operator of an operation
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Expression::TupleAccess(_, Spanned { span, .. })
+            | Expression::Cast(_, Spanned { span, .. })
+            | Expression::Char(Spanned { span, .. })
+            | Expression::FunctionCall(Spanned { span, .. }, _, _)
+            | Expression::Identifier(Spanned { span, .. })
+            | Expression::Float(Spanned { span, .. }, _)
+            | Expression::Int(Spanned { span, .. }, _)
+            | Expression::Operation(_, Spanned { span, .. }, _)
+            | Expression::RepeatedArray(_, Spanned { span, .. })
+            | Expression::String(Spanned { span, .. })
+            | Expression::StructInstantiation(Spanned { span, .. }, _, _)
+            | Expression::Unary(Spanned { span, .. }, _)
+            | Expression::Variable(_, Some(Spanned { span, .. }), _) => {
+                Some(span)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The left hand side of a `let`, which can destructure tuples
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Identifier(Mutability, Spanned<Identifier>),
+    Tuple(Vec<Pattern>),
+}
+
+impl Pattern {
+    /// The span of the first name that the pattern binds
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Pattern::Identifier(_, Spanned { span, .. }) => Some(span),
+            Pattern::Tuple(patterns) => patterns.iter().find_map(Pattern::span),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum TypeName {
     ArrayType(Box<TypeName>),
     Type(Spanned<Identifier>),
     GenericType(Spanned<Identifier>, Vec<Box<TypeName>>),
     Function(Vec<Box<TypeName>>, Box<TypeName>),
+    Tuple(Vec<Box<TypeName>>),
 }
 
 pub type Block = Vec<Box<Statement>>;
+/// The lines of the `///` doc comments directly before a declaration
+pub type Docs = Vec<String>;
 pub type Param = (Identifier, Box<TypeName>);
+/// A function parameter, as in `mut count: Int`
+pub type FunctionParam = (Mutability, Spanned<Identifier>, Box<TypeName>);
 pub type Identifier = String;
 
 #[derive(Clone, Debug)]
@@ -76,12 +135,82 @@ pub enum Operation {
     Subtract,
     Multiply,
     Divide,
+
+    // Bitwise operations are only defined for integers
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+/// The suffix of a numeric literal that sets its type, as in `10i64`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumericSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl NumericSuffix {
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        use NumericSuffix::*;
+        match suffix {
+            "i8" => Some(I8),
+            "i16" => Some(I16),
+            "i32" => Some(I32),
+            "i64" => Some(I64),
+            "u8" => Some(U8),
+            "u16" => Some(U16),
+            "u32" => Some(U32),
+            "u64" => Some(U64),
+            "f32" => Some(F32),
+            "f64" => Some(F64),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, NumericSuffix::F32 | NumericSuffix::F64)
+    }
+
+    /// The name of the Rox type of literals with this suffix
+    pub fn type_name(self) -> &'static str {
+        use NumericSuffix::*;
+        match self {
+            I8 => "Int8",
+            I16 => "Int16",
+            I32 => "Int",
+            I64 => "Int64",
+            U8 => "UInt8",
+            U16 => "UInt16",
+            U32 => "UInt32",
+            U64 => "UInt64",
+            F32 => "Float32",
+            F64 => "Float",
+        }
+    }
+}
+
+/// Whether a variable or parameter can be assigned to after it's declared
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mutability {
+    Immutable,
+    Mutable,
 }
 
 #[derive(Clone, Debug)]
 pub enum Unary {
     Not,
     Negate,
+    BitwiseNot,
 }
 
 #[derive(Clone, Debug)]
@@ -94,19 +223,32 @@ pub struct FunctionDeclaration {
 #[derive(Clone, Debug)]
 pub enum Statement {
     Expression(Box<Expression>),
-    Return(Option<Box<Expression>>),
+    /// The span of the `return` keyword and the returned value
+    Return(Span, Option<Box<Expression>>),
     IfElse(Box<Expression>, Block, Option<Block>),
     ExternFunctionDeclaration(
+        Docs,
         Identifier,
         Vec<Box<TypeName>>,
         Option<Box<TypeName>>,
     ),
     FunctionDeclaration(
-        Identifier,
+        Docs,
+        Spanned<Identifier>,
         Option<Vec<Identifier>>,
-        Vec<Param>,
+        Vec<FunctionParam>,
         Option<Box<TypeName>>,
         Block,
     ),
-    StructDeclaration(Identifier, Option<Vec<Identifier>>, Vec<Param>),
+    StructDeclaration(Docs, Identifier, Option<Vec<Identifier>>, Vec<Param>),
+    /// `const NAME: Type = expression;`, which is evaluated while compiling
+    ConstDeclaration(Docs, Spanned<Identifier>, Box<TypeName>, Box<Expression>),
+    /// `static mut NAME: Type = expression;`, a global variable
+    StaticDeclaration(
+        Docs,
+        Mutability,
+        Spanned<Identifier>,
+        Box<TypeName>,
+        Box<Expression>,
+    ),
 }
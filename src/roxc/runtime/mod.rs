@@ -0,0 +1,965 @@
+//! # Runtime
+//! The runtime module emits the small support library that compiled Rox
+//! programs depend on, such as string concatenation and comparison.
+//!
+//! Rather than linking a prebuilt archive, these functions are generated
+//! directly into every module, so the only thing a Rox program needs at
+//! link time is `libc`. This keeps `rox run` working with `lli` as well.
+//!
+//! ## Strings
+//!
+//! Rox strings are represented as a `{ i8*, i32 }` pair of a pointer to
+//! the bytes and the length of the string. The bytes are always followed
+//! by a NUL terminator (which isn't counted in the length) so that
+//! strings can be handed to `extern` C functions without copying.
+//!
+//! ## `size_t`
+//!
+//! Lengths are passed to `libc` as a `size_t`, which is as wide as a
+//! pointer on the target, so the runtime needs the target's data layout.
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::targets::TargetData;
+use inkwell::types::{
+    BasicType, BasicTypeEnum, FunctionType, IntType, StructType,
+};
+use inkwell::values::{
+    BasicValueEnum, FunctionValue, IntValue, PointerValue, StructValue,
+};
+use inkwell::{AddressSpace, IntPredicate};
+
+pub const ENV: &str = "rox_env";
+pub const FORMAT_BOOL: &str = "rox_format_bool";
+pub const FORMAT_CHAR: &str = "rox_format_char";
+pub const FORMAT_FLOAT: &str = "rox_format_float";
+pub const FORMAT_INT: &str = "rox_format_int";
+pub const FORMAT_INT64: &str = "rox_format_int64";
+pub const FORMAT_UINT64: &str = "rox_format_uint64";
+pub const MAIN: &str = "rox_main";
+pub const PANIC: &str = "rox_panic";
+pub const STRING_CONCAT: &str = "rox_string_concat";
+pub const STRING_COMPARE: &str = "rox_string_compare";
+pub const STRING_FROM_C_STRING: &str = "rox_string_from_c_string";
+pub const STRING_INDEX: &str = "rox_string_index";
+pub const STRING_LEN: &str = "rox_string_len";
+pub const STRING_SUBSTRING: &str = "rox_string_substring";
+
+/// Exit code used when a Rox program panics at runtime
+const PANIC_EXIT_CODE: u64 = 101;
+
+/// Maps the name of a builtin Rox function (see `get_builtin_types`)
+/// to the runtime function that implements it.
+pub(crate) fn builtin_symbol(name: &str) -> Option<&'static str> {
+    match name {
+        "env" => Some(ENV),
+        // `exit` is libc's, which is declared in every module
+        "exit" => Some("exit"),
+        "len" => Some(STRING_LEN),
+        "substring" => Some(STRING_SUBSTRING),
+        _ => None,
+    }
+}
+
+/// The symbol that a Rox function is compiled to. The program's `main` is
+/// renamed so that the C `main` built by `add_entry_point` can call it.
+pub(crate) fn function_symbol(name: &str) -> &str {
+    match name {
+        "main" => MAIN,
+        _ => name,
+    }
+}
+
+/// The LLVM representation of a Rox `String`
+pub(crate) fn string_type(context: &Context) -> StructType {
+    context.struct_type(
+        &[
+            context.i8_type().ptr_type(AddressSpace::Generic).into(),
+            context.i32_type().into(),
+        ],
+        false,
+    )
+}
+
+/// Emits all runtime functions into the given module
+pub(crate) fn add_runtime_functions<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    target_data: &TargetData,
+) {
+    let runtime = RuntimeBuilder {
+        context,
+        module,
+        target_data,
+        builder: context.create_builder(),
+    };
+    runtime.declare_libc_functions();
+    runtime.build_panic();
+    runtime.build_string_len();
+    runtime.build_string_from_c_string();
+    runtime.build_string_concat();
+    runtime.build_string_compare();
+    runtime.build_string_substring();
+    runtime.build_string_index();
+    runtime.build_env();
+    runtime.build_format_int();
+    runtime.build_format_int64();
+    runtime.build_format_float();
+    runtime.build_format_bool();
+    runtime.build_format_char();
+}
+
+/// Emits the C `main(argc, argv)` that calls the program's `main`,
+/// if the module has one
+pub(crate) fn add_entry_point<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    target_data: &TargetData,
+) {
+    if let Some(rox_main) = module.get_function(MAIN) {
+        let runtime = RuntimeBuilder {
+            context,
+            module,
+            target_data,
+            builder: context.create_builder(),
+        };
+        runtime.build_entry_point(rox_main);
+    }
+}
+
+struct RuntimeBuilder<'m, 'ctx> {
+    context: &'ctx Context,
+    module: &'m Module<'ctx>,
+    target_data: &'m TargetData,
+    builder: Builder<'ctx>,
+}
+
+impl<'m, 'ctx> RuntimeBuilder<'m, 'ctx> {
+    fn declare_libc_functions(&self) {
+        let i8_pointer = self.i8_pointer_type();
+        let size = self.size_type();
+        let i32_type = self.context.i32_type();
+        self.declare("malloc", i8_pointer.fn_type(&[size.into()], false));
+        self.declare(
+            "memcpy",
+            i8_pointer.fn_type(
+                &[i8_pointer.into(), i8_pointer.into(), size.into()],
+                false,
+            ),
+        );
+        self.declare(
+            "memcmp",
+            i32_type.fn_type(
+                &[i8_pointer.into(), i8_pointer.into(), size.into()],
+                false,
+            ),
+        );
+        self.declare("strlen", size.fn_type(&[i8_pointer.into()], false));
+        self.declare("getenv", i8_pointer.fn_type(&[i8_pointer.into()], false));
+        self.declare(
+            "write",
+            size.fn_type(
+                &[i32_type.into(), i8_pointer.into(), size.into()],
+                false,
+            ),
+        );
+        self.declare(
+            "snprintf",
+            i32_type.fn_type(
+                &[i8_pointer.into(), size.into(), i8_pointer.into()],
+                true,
+            ),
+        );
+        self.declare(
+            "exit",
+            self.context.void_type().fn_type(&[i32_type.into()], false),
+        );
+    }
+
+    /// `rox_panic(message: String)` writes the message to stderr
+    /// and exits the process.
+    fn build_panic(&self) {
+        let function = self.define(
+            PANIC,
+            self.context
+                .void_type()
+                .fn_type(&[self.string_type().into()], false),
+        );
+        let message = self.param(function, 0).into_struct_value();
+        let stderr = self.context.i32_type().const_int(2, false);
+        self.call(
+            "write",
+            &[
+                stderr.into(),
+                self.string_pointer(message).into(),
+                self.size(self.string_len(message)).into(),
+            ],
+        );
+        let newline = self.builder.build_global_string_ptr("\n", "newline");
+        self.call(
+            "write",
+            &[
+                stderr.into(),
+                newline.as_pointer_value().into(),
+                self.size_type().const_int(1, false).into(),
+            ],
+        );
+        self.call(
+            "exit",
+            &[self
+                .context
+                .i32_type()
+                .const_int(PANIC_EXIT_CODE, false)
+                .into()],
+        );
+        self.builder.build_unreachable();
+    }
+
+    /// `rox_string_len(string: String) -> Int`
+    fn build_string_len(&self) {
+        let function = self.define(
+            STRING_LEN,
+            self.context
+                .i32_type()
+                .fn_type(&[self.string_type().into()], false),
+        );
+        let string = self.param(function, 0).into_struct_value();
+        self.builder.build_return(Some(&self.string_len(string)));
+    }
+
+    /// Wraps a NUL-terminated C string returned from an `extern` function
+    fn build_string_from_c_string(&self) {
+        let function = self.define(
+            STRING_FROM_C_STRING,
+            self.string_type()
+                .fn_type(&[self.i8_pointer_type().into()], false),
+        );
+        let pointer = self.param(function, 0).into_pointer_value();
+        let len = self
+            .call("strlen", &[pointer.into()])
+            .unwrap()
+            .into_int_value();
+        let len = self.builder.build_int_truncate_or_bit_cast(
+            len,
+            self.context.i32_type(),
+            "len",
+        );
+        self.builder
+            .build_return(Some(&self.build_string(pointer, len)));
+    }
+
+    /// `rox_string_concat(left: String, right: String) -> String`
+    fn build_string_concat(&self) {
+        let string_type = self.string_type();
+        let function = self.define(
+            STRING_CONCAT,
+            string_type
+                .fn_type(&[string_type.into(), string_type.into()], false),
+        );
+        let left = self.param(function, 0).into_struct_value();
+        let right = self.param(function, 1).into_struct_value();
+        let left_len = self.string_len(left);
+        let right_len = self.string_len(right);
+        let len = self.builder.build_int_add(left_len, right_len, "len");
+        let buffer = self.allocate_string_buffer(len);
+        self.call(
+            "memcpy",
+            &[
+                buffer.into(),
+                self.string_pointer(left).into(),
+                self.size(left_len).into(),
+            ],
+        );
+        let right_start = unsafe {
+            self.builder
+                .build_in_bounds_gep(buffer, &[left_len], "right_start")
+        };
+        self.call(
+            "memcpy",
+            &[
+                right_start.into(),
+                self.string_pointer(right).into(),
+                self.size(right_len).into(),
+            ],
+        );
+        self.builder
+            .build_return(Some(&self.build_string(buffer, len)));
+    }
+
+    /// `rox_string_compare(left: String, right: String) -> Int`
+    ///
+    /// Returns a negative number, zero or a positive number if `left`
+    /// sorts before, equal to or after `right`, respectively.
+    fn build_string_compare(&self) {
+        let string_type = self.string_type();
+        let function = self.define(
+            STRING_COMPARE,
+            self.context
+                .i32_type()
+                .fn_type(&[string_type.into(), string_type.into()], false),
+        );
+        let left = self.param(function, 0).into_struct_value();
+        let right = self.param(function, 1).into_struct_value();
+        let left_len = self.string_len(left);
+        let right_len = self.string_len(right);
+        let left_is_shorter = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            left_len,
+            right_len,
+            "left_is_shorter",
+        );
+        let shortest_len = self
+            .builder
+            .build_select(left_is_shorter, left_len, right_len, "shortest")
+            .into_int_value();
+        let ordering = self
+            .call(
+                "memcmp",
+                &[
+                    self.string_pointer(left).into(),
+                    self.string_pointer(right).into(),
+                    self.size(shortest_len).into(),
+                ],
+            )
+            .unwrap()
+            .into_int_value();
+        let is_prefix = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            ordering,
+            self.context.i32_type().const_zero(),
+            "is_prefix",
+        );
+        // If one string is a prefix of the other, the shorter one sorts first
+        let len_difference =
+            self.builder.build_int_sub(left_len, right_len, "len_diff");
+        let result = self.builder.build_select(
+            is_prefix,
+            len_difference,
+            ordering,
+            "ordering",
+        );
+        self.builder.build_return(Some(&result));
+    }
+
+    /// `rox_string_substring(string: String, start: Int, end: Int) -> String`
+    ///
+    /// Panics if `start..end` isn't a valid range of the string.
+    fn build_string_substring(&self) {
+        let string_type = self.string_type();
+        let i32_type = self.context.i32_type();
+        let function = self.define(
+            STRING_SUBSTRING,
+            string_type.fn_type(
+                &[string_type.into(), i32_type.into(), i32_type.into()],
+                false,
+            ),
+        );
+        let string = self.param(function, 0).into_struct_value();
+        let start = self.param(function, 1).into_int_value();
+        let end = self.param(function, 2).into_int_value();
+        let string_len = self.string_len(string);
+
+        let start_is_valid = self.builder.build_int_compare(
+            IntPredicate::SGE,
+            start,
+            i32_type.const_zero(),
+            "",
+        );
+        let range_is_ordered =
+            self.builder
+                .build_int_compare(IntPredicate::SLE, start, end, "");
+        let end_is_valid = self.builder.build_int_compare(
+            IntPredicate::SLE,
+            end,
+            string_len,
+            "",
+        );
+        let is_valid = self.builder.build_and(
+            self.builder.build_and(start_is_valid, range_is_ordered, ""),
+            end_is_valid,
+            "in_bounds",
+        );
+        let in_bounds = self.context.append_basic_block(function, "in_bounds");
+        let out_of_bounds =
+            self.context.append_basic_block(function, "out_of_bounds");
+        self.builder.build_conditional_branch(
+            is_valid,
+            in_bounds,
+            out_of_bounds,
+        );
+
+        self.builder.position_at_end(out_of_bounds);
+        self.build_panic_call("String index out of bounds");
+
+        self.builder.position_at_end(in_bounds);
+        let len = self.builder.build_int_sub(end, start, "len");
+        let buffer = self.allocate_string_buffer(len);
+        let source = unsafe {
+            self.builder.build_in_bounds_gep(
+                self.string_pointer(string),
+                &[start],
+                "source",
+            )
+        };
+        self.call(
+            "memcpy",
+            &[buffer.into(), source.into(), self.size(len).into()],
+        );
+        self.builder
+            .build_return(Some(&self.build_string(buffer, len)));
+    }
+
+    /// `rox_string_index(string: String, index: Int) -> String`
+    ///
+    /// Returns the single character string at `index`
+    fn build_string_index(&self) {
+        let string_type = self.string_type();
+        let i32_type = self.context.i32_type();
+        let function = self.define(
+            STRING_INDEX,
+            string_type.fn_type(&[string_type.into(), i32_type.into()], false),
+        );
+        let string = self.param(function, 0);
+        let index = self.param(function, 1).into_int_value();
+        let next =
+            self.builder
+                .build_int_add(index, i32_type.const_int(1, false), "");
+        let substring = self
+            .call(STRING_SUBSTRING, &[string, index.into(), next.into()])
+            .unwrap();
+        self.builder.build_return(Some(&substring));
+    }
+
+    /// `rox_env(name: String) -> String`
+    ///
+    /// Returns an empty string if the variable isn't set
+    fn build_env(&self) {
+        let string_type = self.string_type();
+        let function =
+            self.define(ENV, string_type.fn_type(&[string_type.into()], false));
+        let name = self.param(function, 0).into_struct_value();
+        let value = self
+            .call("getenv", &[self.string_pointer(name).into()])
+            .unwrap()
+            .into_pointer_value();
+        let set = self.context.append_basic_block(function, "set");
+        let unset = self.context.append_basic_block(function, "unset");
+        let is_unset = self.builder.build_is_null(value, "is_unset");
+        self.builder.build_conditional_branch(is_unset, unset, set);
+
+        self.builder.position_at_end(set);
+        let string = self.call(STRING_FROM_C_STRING, &[value.into()]).unwrap();
+        self.builder.build_return(Some(&string));
+
+        self.builder.position_at_end(unset);
+        self.builder
+            .build_return(Some(&self.build_string_constant("")));
+    }
+
+    /// `main(argc: i32, argv: i8**) -> i32`
+    ///
+    /// Calls the program's `main` with `argv` as a `[String]` if it takes
+    /// arguments, and exits with the `Int` it returns, or `0` if it
+    /// doesn't return anything
+    fn build_entry_point(&self, rox_main: FunctionValue<'ctx>) {
+        let i32_type = self.context.i32_type();
+        let argv_type = self
+            .i8_pointer_type()
+            .into_pointer_type()
+            .ptr_type(AddressSpace::Generic);
+        let function = self.module.add_function(
+            "main",
+            i32_type.fn_type(&[i32_type.into(), argv_type.into()], false),
+            None,
+        );
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        let arguments = rox_main
+            .get_type()
+            .get_param_types()
+            .first()
+            .map(|array_type| {
+                self.build_arguments(
+                    function,
+                    self.param(function, 0).into_int_value(),
+                    self.param(function, 1).into_pointer_value(),
+                    array_type.into_struct_type(),
+                )
+                .into()
+            })
+            .into_iter()
+            .collect::<Vec<BasicValueEnum>>();
+        let exit_code = self
+            .builder
+            .build_call(rox_main, &arguments, "")
+            .try_as_basic_value()
+            .left()
+            .map(BasicValueEnum::into_int_value)
+            .unwrap_or_else(|| i32_type.const_zero());
+        self.builder.build_return(Some(&exit_code));
+    }
+
+    /// Copies each of the `argc` C strings in `argv` into a `[String]`
+    fn build_arguments(
+        &self,
+        function: FunctionValue<'ctx>,
+        argc: IntValue<'ctx>,
+        argv: PointerValue<'ctx>,
+        array_type: StructType<'ctx>,
+    ) -> StructValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let string_type = self.string_type();
+        let string_size = self
+            .size_type()
+            .const_int(self.target_data.get_abi_size(&string_type), false);
+        let size =
+            self.builder
+                .build_int_mul(self.size(argc), string_size, "size");
+        let buffer = self
+            .call("malloc", &[size.into()])
+            .unwrap()
+            .into_pointer_value();
+        let strings = self.builder.build_pointer_cast(
+            buffer,
+            string_type.ptr_type(AddressSpace::Generic),
+            "strings",
+        );
+
+        let entry = self.builder.get_insert_block().unwrap();
+        let copy = self.context.append_basic_block(function, "copy");
+        let done = self.context.append_basic_block(function, "done");
+        let zero = i32_type.const_zero();
+        let has_arguments = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            argc,
+            zero,
+            "has_arguments",
+        );
+        self.builder
+            .build_conditional_branch(has_arguments, copy, done);
+
+        self.builder.position_at_end(copy);
+        let index = self.builder.build_phi(i32_type, "index");
+        let index_value = index.as_basic_value().into_int_value();
+        let c_string = unsafe {
+            self.builder.build_in_bounds_gep(argv, &[index_value], "")
+        };
+        let c_string = self.builder.build_load(c_string, "c_string");
+        let string = self.call(STRING_FROM_C_STRING, &[c_string]).unwrap();
+        let element = unsafe {
+            self.builder
+                .build_in_bounds_gep(strings, &[index_value], "")
+        };
+        self.builder.build_store(element, string);
+        let next = self.builder.build_int_add(
+            index_value,
+            i32_type.const_int(1, false),
+            "next",
+        );
+        index.add_incoming(&[(&zero, entry), (&next, copy)]);
+        let has_next = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            next,
+            argc,
+            "has_next",
+        );
+        self.builder.build_conditional_branch(has_next, copy, done);
+
+        self.builder.position_at_end(done);
+        let elements = array_type
+            .get_field_type_at_index(0)
+            .expect("Array type did not have inner array type at index 0")
+            .into_pointer_type();
+        let len_type = array_type
+            .get_field_type_at_index(1)
+            .expect("Array type did not have a length at index 1")
+            .into_int_type();
+        let pointer = self
+            .builder
+            .build_pointer_cast(strings, elements, "elements");
+        let len = self.builder.build_int_cast(argc, len_type, "len");
+        let with_pointer = self
+            .builder
+            .build_insert_value(array_type.get_undef(), pointer, 0, "")
+            .unwrap();
+        self.builder
+            .build_insert_value(with_pointer, len, 1, "")
+            .unwrap()
+            .into_struct_value()
+    }
+
+    /// `rox_format_int(value: Int) -> String`
+    fn build_format_int(&self) {
+        // Enough room for the digits of `i32::MIN` and its sign
+        self.build_format(
+            FORMAT_INT,
+            self.context.i32_type().as_basic_type_enum(),
+            "%d",
+            12,
+        );
+    }
+
+    /// `rox_format_int64(value: Int64) -> String` and
+    /// `rox_format_uint64(value: UInt64) -> String`
+    fn build_format_int64(&self) {
+        // Enough room for the digits of `i64::MIN` and its sign,
+        // which is also enough for `u64::MAX`
+        let i64_type = self.context.i64_type().as_basic_type_enum();
+        self.build_format(FORMAT_INT64, i64_type, "%lld", 21);
+        self.build_format(FORMAT_UINT64, i64_type, "%llu", 21);
+    }
+
+    /// `rox_format_float(value: Float) -> String`
+    fn build_format_float(&self) {
+        self.build_format(
+            FORMAT_FLOAT,
+            self.context.f64_type().as_basic_type_enum(),
+            "%g",
+            32,
+        );
+    }
+
+    /// `rox_format_bool(value: Bool) -> String`
+    fn build_format_bool(&self) {
+        let function = self.define(
+            FORMAT_BOOL,
+            self.string_type()
+                .fn_type(&[self.context.bool_type().into()], false),
+        );
+        let value = self.param(function, 0).into_int_value();
+        let true_string = self.build_string_constant("true");
+        let false_string = self.build_string_constant("false");
+        let result =
+            self.builder
+                .build_select(value, true_string, false_string, "");
+        self.builder.build_return(Some(&result));
+    }
+
+    /// `rox_format_char(value: Char) -> String`
+    ///
+    /// Encodes the character as UTF-8. `Char`s are always valid Unicode
+    /// scalar values, so this doesn't need to handle surrogates.
+    fn build_format_char(&self) {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let function = self.define(
+            FORMAT_CHAR,
+            self.string_type().fn_type(&[i32_type.into()], false),
+        );
+        let value = self.param(function, 0).into_int_value();
+        // The number of bytes in the encoding and the bits that mark
+        // the leading byte as the start of a sequence of that length
+        let mut len = i32_type.const_int(1, false);
+        let mut marker = i32_type.const_zero();
+        for &(start, start_len, start_marker) in
+            &[(0x80, 2, 0xC0), (0x800, 3, 0xE0), (0x10000, 4, 0xF0)]
+        {
+            let is_longer = self.builder.build_int_compare(
+                IntPredicate::UGE,
+                value,
+                i32_type.const_int(start, false),
+                "",
+            );
+            len = self
+                .builder
+                .build_select(
+                    is_longer,
+                    i32_type.const_int(start_len, false),
+                    len,
+                    "len",
+                )
+                .into_int_value();
+            marker = self
+                .builder
+                .build_select(
+                    is_longer,
+                    i32_type.const_int(start_marker, false),
+                    marker,
+                    "marker",
+                )
+                .into_int_value();
+        }
+        let buffer = self.allocate_string_buffer(len);
+
+        // Each continuation byte holds six bits, starting from the end
+        let entry = self.builder.get_insert_block().unwrap();
+        let continuation =
+            self.context.append_basic_block(function, "continuation");
+        let leading = self.context.append_basic_block(function, "leading");
+        let one = i32_type.const_int(1, false);
+        let last = self.builder.build_int_sub(len, one, "last");
+        let has_continuation = self.builder.build_int_compare(
+            IntPredicate::UGT,
+            last,
+            i32_type.const_zero(),
+            "has_continuation",
+        );
+        self.builder.build_conditional_branch(
+            has_continuation,
+            continuation,
+            leading,
+        );
+
+        self.builder.position_at_end(continuation);
+        let index = self.builder.build_phi(i32_type, "index");
+        let index_value = index.as_basic_value().into_int_value();
+        let bits = self.builder.build_phi(i32_type, "bits");
+        let bits_value = bits.as_basic_value().into_int_value();
+        let byte = self.builder.build_or(
+            self.builder.build_and(
+                bits_value,
+                i32_type.const_int(0x3F, false),
+                "",
+            ),
+            i32_type.const_int(0x80, false),
+            "",
+        );
+        let byte = self.builder.build_int_truncate(byte, i8_type, "byte");
+        let pointer = unsafe {
+            self.builder.build_in_bounds_gep(buffer, &[index_value], "")
+        };
+        self.builder.build_store(pointer, byte);
+        let next_index =
+            self.builder.build_int_sub(index_value, one, "next_index");
+        let next_bits = self.builder.build_right_shift(
+            bits_value,
+            i32_type.const_int(6, false),
+            false,
+            "next_bits",
+        );
+        index.add_incoming(&[(&last, entry), (&next_index, continuation)]);
+        bits.add_incoming(&[(&value, entry), (&next_bits, continuation)]);
+        let has_next = self.builder.build_int_compare(
+            IntPredicate::UGT,
+            next_index,
+            i32_type.const_zero(),
+            "has_next",
+        );
+        self.builder
+            .build_conditional_branch(has_next, continuation, leading);
+
+        self.builder.position_at_end(leading);
+        let remaining_bits = self.builder.build_phi(i32_type, "bits");
+        remaining_bits
+            .add_incoming(&[(&value, entry), (&next_bits, continuation)]);
+        let byte = self.builder.build_or(
+            remaining_bits.as_basic_value().into_int_value(),
+            marker,
+            "",
+        );
+        let byte = self.builder.build_int_truncate(byte, i8_type, "byte");
+        self.builder.build_store(buffer, byte);
+        self.builder
+            .build_return(Some(&self.build_string(buffer, len)));
+    }
+
+    /// Builds a function that formats its only parameter
+    /// into a new string using `snprintf`
+    fn build_format(
+        &self,
+        name: &str,
+        value_type: BasicTypeEnum<'ctx>,
+        format: &str,
+        buffer_size: u64,
+    ) {
+        let function =
+            self.define(name, self.string_type().fn_type(&[value_type], false));
+        let value = self.param(function, 0);
+        let size = self.size_type().const_int(buffer_size, false);
+        let buffer = self
+            .call("malloc", &[size.into()])
+            .unwrap()
+            .into_pointer_value();
+        let format = self.builder.build_global_string_ptr(format, "format");
+        let len = self
+            .call(
+                "snprintf",
+                &[
+                    buffer.into(),
+                    size.into(),
+                    format.as_pointer_value().into(),
+                    value,
+                ],
+            )
+            .unwrap()
+            .into_int_value();
+        self.builder
+            .build_return(Some(&self.build_string(buffer, len)));
+    }
+
+    fn build_string_constant(&self, string: &str) -> StructValue<'ctx> {
+        let pointer = self.builder.build_global_string_ptr(string, "");
+        let len = self
+            .context
+            .i32_type()
+            .const_int(string.len() as u64, false);
+        self.build_string(pointer.as_pointer_value(), len)
+    }
+
+    /// Emits a call to `rox_panic` followed by an `unreachable` terminator
+    fn build_panic_call(&self, message: &str) {
+        let message_pointer = self
+            .builder
+            .build_global_string_ptr(message, "panic_message");
+        let len = self
+            .context
+            .i32_type()
+            .const_int(message.len() as u64, false);
+        let message =
+            self.build_string(message_pointer.as_pointer_value(), len);
+        self.call(PANIC, &[message.into()]);
+        self.builder.build_unreachable();
+    }
+
+    /// Allocates `len + 1` bytes on the heap and NUL-terminates them
+    fn allocate_string_buffer(
+        &self,
+        len: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let size = self.builder.build_int_add(
+            self.size(len),
+            self.size_type().const_int(1, false),
+            "size",
+        );
+        let buffer = self
+            .call("malloc", &[size.into()])
+            .unwrap()
+            .into_pointer_value();
+        let terminator = unsafe {
+            self.builder
+                .build_in_bounds_gep(buffer, &[len], "terminator")
+        };
+        self.builder
+            .build_store(terminator, self.context.i8_type().const_zero());
+        buffer
+    }
+
+    fn build_string(
+        &self,
+        pointer: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+    ) -> StructValue<'ctx> {
+        let with_pointer = self
+            .builder
+            .build_insert_value(self.string_type().get_undef(), pointer, 0, "")
+            .unwrap();
+        self.builder
+            .build_insert_value(with_pointer, len, 1, "")
+            .unwrap()
+            .into_struct_value()
+    }
+
+    fn string_pointer(&self, string: StructValue<'ctx>) -> PointerValue<'ctx> {
+        self.builder
+            .build_extract_value(string, 0, "ptr")
+            .unwrap()
+            .into_pointer_value()
+    }
+
+    fn string_len(&self, string: StructValue<'ctx>) -> IntValue<'ctx> {
+        self.builder
+            .build_extract_value(string, 1, "len")
+            .unwrap()
+            .into_int_value()
+    }
+
+    /// Converts an `Int` length into a `size_t`
+    fn size(&self, len: IntValue<'ctx>) -> IntValue<'ctx> {
+        self.builder.build_int_z_extend_or_bit_cast(
+            len,
+            self.size_type(),
+            "size",
+        )
+    }
+
+    /// The target's `size_t`
+    fn size_type(&self) -> IntType<'ctx> {
+        self.context.ptr_sized_int_type(self.target_data, None)
+    }
+
+    fn call(
+        &self,
+        name: &str,
+        args: &[BasicValueEnum<'ctx>],
+    ) -> Option<BasicValueEnum<'ctx>> {
+        let function = self
+            .module
+            .get_function(name)
+            .unwrap_or_else(|| panic!("Runtime function {} is missing", name));
+        self.builder
+            .build_call(function, args, "")
+            .try_as_basic_value()
+            .left()
+    }
+
+    fn param(
+        &self,
+        function: FunctionValue<'ctx>,
+        index: u32,
+    ) -> BasicValueEnum<'ctx> {
+        function.get_nth_param(index).unwrap()
+    }
+
+    /// Adds a private runtime function and positions the builder in its body
+    fn define(
+        &self,
+        name: &str,
+        fn_type: FunctionType<'ctx>,
+    ) -> FunctionValue<'ctx> {
+        let function =
+            self.module
+                .add_function(name, fn_type, Some(Linkage::Private));
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        function
+    }
+
+    /// Declares an external function unless the module already has one
+    fn declare(&self, name: &str, fn_type: FunctionType<'ctx>) {
+        if self.module.get_function(name).is_none() {
+            self.module.add_function(name, fn_type, None);
+        }
+    }
+
+    fn string_type(&self) -> StructType<'ctx> {
+        string_type(self.context)
+    }
+
+    fn i8_pointer_type(&self) -> BasicTypeEnum<'ctx> {
+        self.context
+            .i8_type()
+            .ptr_type(AddressSpace::Generic)
+            .as_basic_type_enum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roxc::{OptimizationLevel, TargetOptions};
+
+    fn param_width(module: &Module, name: &str, index: usize) -> u32 {
+        let function = module.get_function(name).unwrap();
+        match function.get_type().get_param_types()[index] {
+            BasicTypeEnum::IntType(int_type) => int_type.get_bit_width(),
+            other => panic!("Expected an integer, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_uses_the_targets_size_t() {
+        let options = TargetOptions {
+            triple: Some("armv7-unknown-linux-gnueabihf".to_string()),
+            ..Default::default()
+        };
+        let target_machine = options
+            .create_target_machine(OptimizationLevel::O0)
+            .unwrap();
+        let target_data = target_machine.get_target_data();
+        let context = Context::create();
+        let module = context.create_module("test");
+        add_runtime_functions(&context, &module, &target_data);
+        assert_eq!(param_width(&module, "malloc", 0), 32);
+        assert_eq!(param_width(&module, "memcpy", 2), 32);
+    }
+}
@@ -0,0 +1,566 @@
+//! # Constant evaluation
+//! Evaluates expressions whose operands are all literals while type checking,
+//! so that `2 * 3` is emitted as `6` instead of a runtime `mul`. The same
+//! evaluator gives `const` initializers and array lengths their values, and
+//! reports division by zero and overflow as compile-time errors.
+//!
+//! Integer literals store their value as a `u64`, so negative values are kept
+//! in two's complement and truncated to the literal's type by LLVM.
+
+use crate::roxc::semant::types::{Type, TypeConstructor};
+use crate::roxc::semant::TaggedExpression;
+use crate::roxc::{Operation, Result, RoxError, Span, Spanned, Unary};
+
+/// A value known at compile time
+#[derive(Clone, Debug, PartialEq)]
+enum Constant {
+    Bool(bool),
+    Char(char),
+    /// Integers are widened to `i128`, which can hold every Rox integer
+    Int(i128, TypeConstructor),
+    Float(f64, TypeConstructor),
+    String(String),
+}
+
+impl Constant {
+    fn type_constructor(&self) -> TypeConstructor {
+        match self {
+            Constant::Bool(_) => TypeConstructor::Bool,
+            Constant::Char(_) => TypeConstructor::Char,
+            Constant::Int(_, constructor) | Constant::Float(_, constructor) => {
+                constructor.clone()
+            }
+            Constant::String(_) => TypeConstructor::String,
+        }
+    }
+}
+
+/// Replaces an expression with the literal it evaluates to, if all of
+/// its operands are known at compile time
+pub(crate) fn fold(expression: TaggedExpression) -> Result<TaggedExpression> {
+    Ok(evaluate(&expression)?.unwrap_or(expression))
+}
+
+/// Evaluates an expression to a literal, or `None` if it depends on
+/// values that are only known at runtime
+pub(crate) fn evaluate(
+    expression: &TaggedExpression,
+) -> Result<Option<TaggedExpression>> {
+    match expression {
+        TaggedExpression::Boolean(_)
+        | TaggedExpression::Char(_)
+        | TaggedExpression::Float(_, _)
+        | TaggedExpression::Int(_, _)
+        | TaggedExpression::String(_) => Ok(Some(expression.clone())),
+        TaggedExpression::Tuple(elements, type_) => {
+            let values = elements
+                .iter()
+                .map(evaluate)
+                .collect::<Result<Option<Vec<_>>>>()?;
+            Ok(values
+                .map(|values| TaggedExpression::Tuple(values, type_.clone())))
+        }
+        TaggedExpression::TupleAccess(tuple, index, _) => {
+            match evaluate(tuple)? {
+                Some(TaggedExpression::Tuple(mut elements, _)) => {
+                    Ok(Some(elements.swap_remove(index.value)))
+                }
+                _ => Ok(None),
+            }
+        }
+        TaggedExpression::Operation(left, operation, right, _) => {
+            match (constant(left)?, constant(right)?) {
+                (Some(left), Some(right)) => {
+                    evaluate_operation(left, operation, right)
+                        .map(|value| Some(literal(value, &operation.span)))
+                }
+                _ => Ok(None),
+            }
+        }
+        TaggedExpression::Unary(unary, operand, _) => {
+            let span = operand.span().unwrap_or(&unary.span).clone();
+            match constant(operand)? {
+                Some(operand) => evaluate_unary(unary, operand)
+                    .map(|value| Some(literal(value, &span))),
+                None => Ok(None),
+            }
+        }
+        TaggedExpression::And(left, right)
+        | TaggedExpression::Or(left, right) => {
+            let is_and = matches!(expression, TaggedExpression::And(..));
+            match (constant(left)?, constant(right)?) {
+                (Some(Constant::Bool(left)), Some(Constant::Bool(right))) => {
+                    let value =
+                        if is_and { left && right } else { left || right };
+                    Ok(Some(TaggedExpression::Boolean(value)))
+                }
+                _ => Ok(None),
+            }
+        }
+        TaggedExpression::Cast(value, type_, span) => {
+            let span = value.span().unwrap_or(span).clone();
+            match constant(value)? {
+                Some(value) => Ok(evaluate_cast(value, constructor(type_))
+                    .map(|value| literal(value, &span))),
+                None => Ok(None),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+fn constant(expression: &TaggedExpression) -> Result<Option<Constant>> {
+    Ok(evaluate(expression)?.and_then(|literal| match literal {
+        TaggedExpression::Boolean(boolean) => Some(Constant::Bool(boolean)),
+        TaggedExpression::Char(character) => {
+            Some(Constant::Char(character.value))
+        }
+        TaggedExpression::Int(int, type_) => {
+            let constructor = constructor(&type_);
+            Some(Constant::Int(
+                from_bits(int.value, &constructor),
+                constructor,
+            ))
+        }
+        TaggedExpression::Float(float, type_) => {
+            Some(Constant::Float(float.value, constructor(&type_)))
+        }
+        TaggedExpression::String(string) => {
+            Some(Constant::String(string.value))
+        }
+        _ => None,
+    }))
+}
+
+fn constructor(type_: &Type) -> TypeConstructor {
+    match type_.without_empty_quantifier() {
+        Type::Apply(constructor, _) => constructor.clone(),
+        type_ => unreachable!("Literals have concrete types, not {:?}", type_),
+    }
+}
+
+fn literal(value: Constant, span: &Span) -> TaggedExpression {
+    let span = span.clone();
+    match value {
+        Constant::Bool(boolean) => TaggedExpression::Boolean(boolean),
+        Constant::Char(character) => TaggedExpression::Char(Spanned {
+            value: character,
+            span,
+        }),
+        Constant::Int(int, constructor) => TaggedExpression::Int(
+            Spanned {
+                value: int as u64,
+                span,
+            },
+            Box::new(Type::Apply(constructor, Vec::new())),
+        ),
+        Constant::Float(float, constructor) => TaggedExpression::Float(
+            Spanned { value: float, span },
+            Box::new(Type::Apply(constructor, Vec::new())),
+        ),
+        Constant::String(string) => TaggedExpression::String(Spanned {
+            value: string,
+            span,
+        }),
+    }
+}
+
+/// Reads the two's complement bits of an integer literal
+fn from_bits(bits: u64, constructor: &TypeConstructor) -> i128 {
+    wrap(bits as i128, constructor)
+}
+
+/// Truncates an integer to the width of its type, as the integer
+/// operations that can't overflow do at runtime
+fn wrap(int: i128, constructor: &TypeConstructor) -> i128 {
+    let width = constructor.bit_width().expect("Expected an integer type");
+    let truncated = int & ((1 << width) - 1);
+    if constructor.is_signed() && truncated >> (width - 1) == 1 {
+        truncated - (1 << width)
+    } else {
+        truncated
+    }
+}
+
+fn checked_int(
+    int: Option<i128>,
+    constructor: TypeConstructor,
+    operation: &Spanned<Operation>,
+) -> Result<Constant> {
+    match int {
+        Some(int) if wrap(int, &constructor) == int => {
+            Ok(Constant::Int(int, constructor))
+        }
+        _ => Err(RoxError::with_file_placeholder(
+            "This arithmetic operation will overflow",
+        )
+        .with_label(
+            &format!("overflows {:?}", constructor),
+            operation.span.clone(),
+        )),
+    }
+}
+
+fn evaluate_operation(
+    left: Constant,
+    operation: &Spanned<Operation>,
+    right: Constant,
+) -> Result<Constant> {
+    use Operation::*;
+    match (left, right) {
+        (Constant::Int(left, constructor), Constant::Int(right, _)) => {
+            let width = constructor.bit_width().unwrap() as i128;
+            match operation.value {
+                Add => {
+                    checked_int(left.checked_add(right), constructor, operation)
+                }
+                Subtract => {
+                    checked_int(left.checked_sub(right), constructor, operation)
+                }
+                Multiply => {
+                    checked_int(left.checked_mul(right), constructor, operation)
+                }
+                Divide if right == 0 => Err(RoxError::with_file_placeholder(
+                    "This operation will divide by zero",
+                )
+                .with_label(
+                    "attempt to divide by zero",
+                    operation.span.clone(),
+                )),
+                Divide => {
+                    checked_int(left.checked_div(right), constructor, operation)
+                }
+                BitwiseAnd => Ok(Constant::Int(left & right, constructor)),
+                BitwiseOr => Ok(Constant::Int(left | right, constructor)),
+                BitwiseXor => Ok(Constant::Int(left ^ right, constructor)),
+                ShiftLeft | ShiftRight if right < 0 || right >= width => {
+                    Err(RoxError::with_file_placeholder(
+                        "This shift will overflow",
+                    )
+                    .with_label(
+                        &format!("shifts a {}-bit integer by {}", width, right),
+                        operation.span.clone(),
+                    ))
+                }
+                ShiftLeft => Ok(Constant::Int(
+                    wrap(left << right, &constructor),
+                    constructor,
+                )),
+                ShiftRight => Ok(Constant::Int(left >> right, constructor)),
+                Equals => Ok(Constant::Bool(left == right)),
+                NotEquals => Ok(Constant::Bool(left != right)),
+                GreaterThan => Ok(Constant::Bool(left > right)),
+                LessThan => Ok(Constant::Bool(left < right)),
+            }
+        }
+        (Constant::Float(left, constructor), Constant::Float(right, _)) => {
+            let float = |value: f64| {
+                Constant::Float(
+                    round_float(value, &constructor),
+                    constructor.clone(),
+                )
+            };
+            match operation.value {
+                Add => Ok(float(left + right)),
+                Subtract => Ok(float(left - right)),
+                Multiply => Ok(float(left * right)),
+                Divide => Ok(float(left / right)),
+                Equals => Ok(Constant::Bool(left == right)),
+                NotEquals => Ok(Constant::Bool(left != right)),
+                GreaterThan => Ok(Constant::Bool(left > right)),
+                LessThan => Ok(Constant::Bool(left < right)),
+                BitwiseAnd | BitwiseOr | BitwiseXor | ShiftLeft
+                | ShiftRight => unreachable!(
+                    "Bitwise operations on floats are rejected while type checking"
+                ),
+            }
+        }
+        (Constant::String(left), Constant::String(right)) => {
+            match operation.value {
+                Add => Ok(Constant::String(left + &right)),
+                Equals => Ok(Constant::Bool(left == right)),
+                NotEquals => Ok(Constant::Bool(left != right)),
+                GreaterThan => Ok(Constant::Bool(left > right)),
+                LessThan => Ok(Constant::Bool(left < right)),
+                _ => unreachable!(
+                    "Strings only support `+` and comparisons after type checking"
+                ),
+            }
+        }
+        (left, right) => match operation.value {
+            Equals => Ok(Constant::Bool(left == right)),
+            NotEquals => Ok(Constant::Bool(left != right)),
+            GreaterThan => {
+                Ok(Constant::Bool(char_code(&left) > char_code(&right)))
+            }
+            LessThan => {
+                Ok(Constant::Bool(char_code(&left) < char_code(&right)))
+            }
+            _ => unreachable!(
+                "Operation {:?} should have been rejected while type checking",
+                operation.value
+            ),
+        },
+    }
+}
+
+fn char_code(constant: &Constant) -> u32 {
+    match constant {
+        Constant::Char(character) => *character as u32,
+        constant => {
+            unreachable!("Only chars can be ordered, not {:?}", constant)
+        }
+    }
+}
+
+/// Rounds a float to the precision of its type
+fn round_float(float: f64, constructor: &TypeConstructor) -> f64 {
+    match constructor {
+        TypeConstructor::Float32 => float as f32 as f64,
+        _ => float,
+    }
+}
+
+fn evaluate_unary(
+    unary: &Spanned<Unary>,
+    operand: Constant,
+) -> Result<Constant> {
+    match (&unary.value, operand) {
+        (Unary::Not, Constant::Bool(boolean)) => Ok(Constant::Bool(!boolean)),
+        (Unary::Negate, Constant::Float(float, constructor)) => {
+            Ok(Constant::Float(-float, constructor))
+        }
+        (Unary::Negate, Constant::Int(int, constructor)) => {
+            if wrap(-int, &constructor) == -int {
+                Ok(Constant::Int(-int, constructor))
+            } else {
+                Err(RoxError::with_file_placeholder(&format!(
+                    "Negating {} will overflow {:?}",
+                    int, constructor
+                ))
+                .with_label(
+                    &format!("overflows {:?}", constructor),
+                    unary.span.clone(),
+                ))
+            }
+        }
+        (Unary::BitwiseNot, Constant::Int(int, constructor)) => {
+            Ok(Constant::Int(wrap(!int, &constructor), constructor))
+        }
+        (unary, operand) => unreachable!(
+            "Unary {:?} on {:?} should have been rejected while type checking",
+            unary, operand
+        ),
+    }
+}
+
+/// Casts a constant with the same conversions as `CompilerState::build_cast`,
+/// or `None` for casts whose result isn't a valid value, such as integers
+/// that aren't Unicode scalar values being cast to `Char`
+fn evaluate_cast(value: Constant, to: TypeConstructor) -> Option<Constant> {
+    if value.type_constructor() == to {
+        return Some(value);
+    }
+    match (value, to) {
+        (Constant::Int(int, _), TypeConstructor::Char) => {
+            std::char::from_u32(int as u32).map(Constant::Char)
+        }
+        (Constant::Char(character), to) if to.is_integer() => {
+            Some(Constant::Int(wrap(character as i128, &to), to))
+        }
+        (Constant::Bool(boolean), to) if to.is_integer() => {
+            Some(Constant::Int(boolean as i128, to))
+        }
+        (Constant::Int(int, _), to) if to.is_integer() => {
+            Some(Constant::Int(wrap(int, &to), to))
+        }
+        (Constant::Int(int, _), to) => {
+            Some(Constant::Float(round_float(int as f64, &to), to))
+        }
+        (Constant::Float(float, _), to) if to.is_float() => {
+            Some(Constant::Float(round_float(float, &to), to))
+        }
+        // Out of range floats saturate, as with Rust's `as`
+        (Constant::Float(float, _), to) => {
+            Some(Constant::Int(clamp_float(float, &to), to))
+        }
+        (value, to) => unreachable!(
+            "Casting {:?} to {:?} should have been rejected while type checking",
+            value, to
+        ),
+    }
+}
+
+fn clamp_float(float: f64, constructor: &TypeConstructor) -> i128 {
+    let max = constructor.max_int().unwrap() as i128;
+    let min = if constructor.is_signed() { -max - 1 } else { 0 };
+    (float as i128).max(min).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(value: i128, constructor: TypeConstructor) -> TaggedExpression {
+        literal(Constant::Int(value, constructor), &Span(0, 0))
+    }
+
+    fn operation(
+        left: TaggedExpression,
+        operation: Operation,
+        right: TaggedExpression,
+    ) -> TaggedExpression {
+        let operand_type = Type::from(left.clone());
+        TaggedExpression::Operation(
+            Box::new(left),
+            Spanned {
+                value: operation,
+                span: Span(4, 5),
+            },
+            Box::new(right),
+            Box::new(operand_type),
+        )
+    }
+
+    #[test]
+    fn it_folds_nested_arithmetic() {
+        let product = operation(
+            int(2, TypeConstructor::Int),
+            Operation::Multiply,
+            int(3, TypeConstructor::Int),
+        );
+        let difference = operation(
+            int(1, TypeConstructor::Int),
+            Operation::Subtract,
+            product,
+        );
+
+        assert!(matches!(
+            fold(difference).unwrap(),
+            TaggedExpression::Int(value, _) if value.value as i32 == -5
+        ));
+    }
+
+    #[test]
+    fn it_reports_division_by_zero_at_the_operator() {
+        let error = fold(operation(
+            int(1, TypeConstructor::Int),
+            Operation::Divide,
+            int(0, TypeConstructor::Int),
+        ))
+        .unwrap_err();
+
+        assert_eq!(error.labels[0].range, 4..5);
+    }
+
+    #[test]
+    fn it_reports_overflow_for_the_operand_type() {
+        let sum = |constructor: TypeConstructor| {
+            fold(operation(
+                int(200, constructor.clone()),
+                Operation::Add,
+                int(100, constructor),
+            ))
+        };
+
+        assert!(sum(TypeConstructor::UInt8).is_err());
+        assert!(sum(TypeConstructor::Int16).is_ok());
+        assert!(fold(operation(
+            int(0, TypeConstructor::UInt32),
+            Operation::Subtract,
+            int(1, TypeConstructor::UInt32),
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn it_reports_negation_overflow_at_the_operator() {
+        let negation = TaggedExpression::Unary(
+            Spanned {
+                value: Unary::Negate,
+                span: Span(2, 3),
+            },
+            Box::new(int(i32::MIN.into(), TypeConstructor::Int)),
+            Box::new(Type::Apply(TypeConstructor::Int, Vec::new())),
+        );
+
+        let error = fold(negation).unwrap_err();
+
+        assert_eq!(error.labels[0].range, 2..3);
+    }
+
+    #[test]
+    fn it_keeps_the_span_of_folded_casts() {
+        let float_type = Type::Apply(TypeConstructor::Float, Vec::new());
+        let cast = TaggedExpression::Cast(
+            Box::new(literal(
+                Constant::Int(1, TypeConstructor::Int),
+                &Span(5, 6),
+            )),
+            Box::new(float_type),
+            Span(10, 15),
+        );
+
+        assert!(matches!(
+            fold(cast).unwrap(),
+            TaggedExpression::Float(float, _)
+                if (float.span.0, float.span.1) == (5, 6)
+        ));
+    }
+
+    #[test]
+    fn it_folds_casts_to_the_same_type() {
+        let cast = |value: Constant, type_constructor: TypeConstructor| {
+            fold(TaggedExpression::Cast(
+                Box::new(literal(value, &Span(0, 3))),
+                Box::new(Type::Apply(type_constructor, Vec::new())),
+                Span(7, 11),
+            ))
+            .unwrap()
+        };
+
+        assert!(matches!(
+            cast(Constant::Char('a'), TypeConstructor::Char),
+            TaggedExpression::Char(character) if character.value == 'a'
+        ));
+        assert!(matches!(
+            cast(Constant::Bool(true), TypeConstructor::Bool),
+            TaggedExpression::Boolean(true)
+        ));
+    }
+
+    #[test]
+    fn it_concatenates_strings() {
+        let string = |value: &str| {
+            literal(Constant::String(value.to_string()), &Span(0, 0))
+        };
+        let concatenated = TaggedExpression::Operation(
+            Box::new(string("con")),
+            Spanned::dummy_span(Operation::Add),
+            Box::new(string("cat")),
+            Box::new(Type::Apply(TypeConstructor::String, Vec::new())),
+        );
+
+        assert!(matches!(
+            fold(concatenated).unwrap(),
+            TaggedExpression::String(string) if string.value == "concat"
+        ));
+    }
+
+    #[test]
+    fn it_leaves_runtime_values_alone() {
+        let sum = operation(
+            TaggedExpression::Identifier(
+                Spanned::dummy_span("x".to_string()),
+                Box::new(Type::Apply(TypeConstructor::Int, Vec::new())),
+            ),
+            Operation::Add,
+            int(1, TypeConstructor::Int),
+        );
+
+        assert!(matches!(
+            fold(sum).unwrap(),
+            TaggedExpression::Operation(..)
+        ));
+    }
+}
@@ -29,10 +29,12 @@
 // //! reader, they can also potentially act as clutter. They also pose more
 // //! work to the programmer when refactoring and changing types.
 
+mod const_eval;
 mod tagged_syntax;
 mod type_checker;
 mod types;
 
+pub(crate) use const_eval::{evaluate, fold};
 pub(crate) use tagged_syntax::*;
 pub(crate) use type_checker::*;
 pub(crate) use types::*;
@@ -6,8 +6,11 @@
 
 use crate::roxc::parser;
 use crate::roxc::semant::types::{Type, TypeConstructor};
-use crate::roxc::{semant, FunctionDeclaration, Identifier, Operation, Unary};
-use parser::Spanned;
+use crate::roxc::{
+    semant, FunctionDeclaration, Identifier, Mutability, Operation, Pattern,
+    Unary,
+};
+use parser::{Span, Spanned};
 
 #[derive(Clone, Debug)]
 pub struct TaggedLValue(pub TaggedExpression);
@@ -16,14 +19,21 @@ pub struct TaggedLValue(pub TaggedExpression);
 #[allow(clippy::vec_box, dead_code)]
 pub enum TaggedExpression {
     BracketAccess(Box<TaggedExpression>, Box<TaggedExpression>, Box<Type>),
+    DotAccess(Box<TaggedExpression>, Identifier, Box<Type>),
+    TupleAccess(Box<TaggedExpression>, Spanned<usize>, Box<Type>),
     And(Box<TaggedExpression>, Box<TaggedExpression>),
     Array(Vec<TaggedExpression>, Box<Type>),
     Assignment(Box<TaggedLValue>, Box<TaggedExpression>, Box<Type>),
     Boolean(bool),
+    /// The expression being cast, the type it's cast to and the
+    /// span of the type's name
+    Cast(Box<TaggedExpression>, Box<Type>, Span),
+    Char(Spanned<char>),
     FunctionCall(Spanned<Identifier>, Vec<TaggedExpression>, Box<Type>),
     Identifier(Spanned<Identifier>, Box<Type>),
-    Float(Spanned<f64>),
-    Int(Spanned<i32>),
+    InterpolatedString(Vec<TaggedExpression>),
+    Float(Spanned<f64>, Box<Type>),
+    Int(Spanned<u64>, Box<Type>),
     Operation(
         Box<TaggedExpression>,
         Spanned<Operation>,
@@ -31,10 +41,33 @@ pub enum TaggedExpression {
         Box<Type>,
     ),
     Or(Box<TaggedExpression>, Box<TaggedExpression>),
+    /// The repeated value, the evaluated length and the array type
+    RepeatedArray(Box<TaggedExpression>, usize, Box<Type>),
     String(Spanned<String>),
     StructInstantiation(Box<Type>, Vec<(Identifier, Box<TaggedExpression>)>),
-    Unary(Unary, Box<TaggedExpression>, Box<Type>),
-    Variable(Spanned<Identifier>, Box<TaggedExpression>, Box<Type>),
+    Tuple(Vec<TaggedExpression>, Box<Type>),
+    Unary(Spanned<Unary>, Box<TaggedExpression>, Box<Type>),
+    Variable(Pattern, Box<TaggedExpression>, Box<Type>),
+}
+
+impl TaggedExpression {
+    /// The span of the token that identifies this expression, such as
+    /// the name of a called function or the operator of an operation
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            TaggedExpression::TupleAccess(_, Spanned { span, .. }, _)
+            | TaggedExpression::Cast(_, _, span)
+            | TaggedExpression::Char(Spanned { span, .. })
+            | TaggedExpression::FunctionCall(Spanned { span, .. }, _, _)
+            | TaggedExpression::Identifier(Spanned { span, .. }, _)
+            | TaggedExpression::Float(Spanned { span, .. }, _)
+            | TaggedExpression::Int(Spanned { span, .. }, _)
+            | TaggedExpression::Operation(_, Spanned { span, .. }, _, _)
+            | TaggedExpression::Unary(Spanned { span, .. }, _, _)
+            | TaggedExpression::String(Spanned { span, .. }) => Some(span),
+            _ => None,
+        }
+    }
 }
 
 impl From<TaggedLValue> for semant::Type {
@@ -48,30 +81,41 @@ impl From<TaggedExpression> for semant::Type {
         use TaggedExpression::*;
         match expr {
             BracketAccess(_, _, t)
+            | DotAccess(_, _, t)
+            | TupleAccess(_, _, t)
+            | Tuple(_, t)
             | Array(_, t)
+            | RepeatedArray(_, _, t)
             | Assignment(_, _, t)
+            | Cast(_, t, _)
             | FunctionCall(_, _, t)
             | StructInstantiation(t, _)
             | Unary(_, _, t)
-            | Identifier(_, t) => t.as_ref().clone(),
+            | Identifier(_, t)
+            | Float(_, t)
+            | Int(_, t) => t.as_ref().clone(),
             And(_, _) | Or(_, _) | Boolean(_) => {
                 Type::Apply(TypeConstructor::Bool, Vec::new())
             }
-            Float(_) => Type::Apply(TypeConstructor::Float, Vec::new()),
-            Int(_) => Type::Apply(TypeConstructor::Int, Vec::new()),
-            String(_) => Type::Apply(TypeConstructor::String, Vec::new()),
-            Operation(_, operation, _, _) => {
+            Char(_) => Type::Apply(TypeConstructor::Char, Vec::new()),
+            String(_) | InterpolatedString(_) => {
+                Type::Apply(TypeConstructor::String, Vec::new())
+            }
+            Operation(_, operation, _, operand_type) => {
                 use parser::Operation::*;
                 match operation.value {
                     Equals | NotEquals | GreaterThan | LessThan => {
                         Type::Apply(TypeConstructor::Bool, Vec::new())
                     }
-                    Add | Subtract | Multiply | Divide => {
-                        Type::Apply(TypeConstructor::Float, Vec::new())
+                    Add | Subtract | Multiply | Divide | BitwiseAnd
+                    | BitwiseOr | BitwiseXor | ShiftLeft | ShiftRight => {
+                        *operand_type
                     }
                 }
             }
-            Variable(_, _, _) => todo!(),
+            Variable(_, _, _) => {
+                unreachable!("`let` is only type checked as a statement")
+            }
         }
     }
 }
@@ -84,6 +128,11 @@ pub(crate) enum TaggedStatement {
     ExternFunctionDeclaration(FunctionDeclaration),
     FunctionDeclaration(FunctionDeclaration, TaggedBlock),
     StructDeclaration,
+    /// Constants are inlined where they're used, so they need no code
+    ConstDeclaration,
+    /// The name, mutability, type and constant initial value of a global
+    /// variable
+    StaticDeclaration(Identifier, Mutability, Type, TaggedExpression),
     IfElse(Box<TaggedExpression>, TaggedBlock, Option<TaggedBlock>),
     Return(Option<TaggedExpression>),
 }
@@ -21,15 +21,16 @@
 //! valid programs.
 //! To do this, we need to verify that all the application of our types are equal, or "unified."
 use crate::roxc::{
-    builtins, Expression, FunctionDeclaration, Identifier, Result, RoxError,
-    Statement, TypeName, Unary,
+    builtins, not_yet_supported, record_span, Expression, FunctionDeclaration,
+    Identifier, Mutability, NumericSuffix, Operation, Pattern, Result,
+    RoxError, Span, Statement, TypeName, Unary,
 };
 use crate::roxc::{
     semant::types::{Type, TypeConstructor},
     Spanned,
 };
 use crate::roxc::{
-    semant::{TaggedExpression, TaggedStatement},
+    semant::{evaluate, fold, TaggedExpression, TaggedStatement},
     LValue,
 };
 use std::collections::HashMap;
@@ -37,7 +38,45 @@ use std::collections::HashMap;
 use super::TaggedLValue;
 
 pub(crate) type TypeEnv = HashMap<Identifier, Type>;
-pub(crate) type VariableEnv = HashMap<Identifier, Type>;
+pub(crate) type VariableEnv = HashMap<Identifier, Binding>;
+
+/// A name in scope, along with whether it can be assigned to
+#[derive(Clone, Debug)]
+pub(crate) struct Binding {
+    pub type_: Type,
+    pub mutability: Mutability,
+    /// Where the name was declared, for variables and parameters
+    pub declaration: Option<Span>,
+    /// The value of a `const`, which is inlined wherever it's used
+    pub value: Option<TaggedExpression>,
+    /// Whether the name is a `static`, which is made mutable with
+    /// `static mut` rather than `mut`
+    pub is_static: bool,
+}
+
+impl Binding {
+    /// A binding for a function or struct, which can't be assigned to
+    pub fn item(type_: Type) -> Self {
+        Binding {
+            type_,
+            mutability: Mutability::Immutable,
+            declaration: None,
+            value: None,
+            is_static: false,
+        }
+    }
+
+    /// A binding for a variable, parameter or static declared at `span`
+    pub fn variable(type_: Type, mutability: Mutability, span: Span) -> Self {
+        Binding {
+            type_,
+            mutability,
+            declaration: Some(span),
+            value: None,
+            is_static: false,
+        }
+    }
+}
 
 fn substitute(ty: Type, env: &mut TypeEnv) -> Type {
     match ty {
@@ -51,6 +90,19 @@ fn substitute(ty: Type, env: &mut TypeEnv) -> Type {
                     env,
                 )
             }
+            TypeConstructor::Array(inner_type) => Type::Apply(
+                TypeConstructor::Array(Box::new(substitute(*inner_type, env))),
+                type_arguments,
+            ),
+            TypeConstructor::Tuple(element_types) => Type::Apply(
+                TypeConstructor::Tuple(
+                    element_types
+                        .into_iter()
+                        .map(|t| substitute(t, env))
+                        .collect(),
+                ),
+                type_arguments,
+            ),
             _ => Type::Apply(
                 type_constructor,
                 type_arguments
@@ -82,6 +134,8 @@ fn substitute(ty: Type, env: &mut TypeEnv) -> Type {
 }
 
 fn unify(type_one: Type, type_two: Type) -> Result<()> {
+    let type_one = type_one.without_empty_quantifier().clone();
+    let type_two = type_two.without_empty_quantifier().clone();
     match (type_one.clone(), type_two.clone()) {
         (Type::Variable(first), Type::Variable(second)) => {
             if first == second {
@@ -131,6 +185,25 @@ fn unify(type_one: Type, type_two: Type) -> Result<()> {
                     &mut zip_argument_types(formal_types, first_type_arguments),
                 ),
             ),
+            (
+                TypeConstructor::Tuple(first_elements),
+                TypeConstructor::Tuple(second_elements),
+            ) => {
+                if first_elements.len() != second_elements.len() {
+                    return Err(RoxError::with_file_placeholder(
+                        format!(
+                            "Type mismatch: a tuple with {} elements is not a tuple with {} elements",
+                            first_elements.len(),
+                            second_elements.len()
+                        )
+                        .as_ref(),
+                    ));
+                }
+                first_elements
+                    .into_iter()
+                    .zip(second_elements)
+                    .try_for_each(|(first, second)| unify(first, second))
+            }
             (
                 TypeConstructor::Unique(first_constructor),
                 TypeConstructor::Unique(second_constructor),
@@ -152,6 +225,24 @@ fn unify(type_one: Type, type_two: Type) -> Result<()> {
                         })
                 }
             }
+            (
+                TypeConstructor::Array(first_inner),
+                TypeConstructor::Array(second_inner),
+            ) => unify(*first_inner, *second_inner),
+            // Records and generic types are compared by their type arguments
+            (first, second)
+                if first != second
+                    && !is_structural(&first)
+                    && !is_structural(&second) =>
+            {
+                Err(RoxError::with_file_placeholder(
+                    format!(
+                        "Type mismatch: attempted to unify {:?} and {:?}",
+                        first, second
+                    )
+                    .as_ref(),
+                ))
+            }
             (_, _) => first_type_arguments
                 .iter()
                 .zip(second_type_arguments)
@@ -164,6 +255,15 @@ fn unify(type_one: Type, type_two: Type) -> Result<()> {
     }
 }
 
+fn is_structural(constructor: &TypeConstructor) -> bool {
+    matches!(
+        constructor,
+        TypeConstructor::Record(_)
+            | TypeConstructor::FunctionType(_, _)
+            | TypeConstructor::Unique(_)
+    )
+}
+
 fn expand(t: Type) -> Type {
     match t.clone() {
         Type::Apply(constructor, types) => match constructor {
@@ -186,13 +286,17 @@ fn zip_argument_types(names: Vec<Identifier>, types: Vec<Type>) -> TypeEnv {
     names.iter().cloned().zip(types).collect::<HashMap<_, _>>()
 }
 
+/// Type checks a statement. `return_type` is the declared return type of
+/// the function that the statement is in, if it's in one.
 fn translate_statement(
     type_env: &mut TypeEnv,
     variable_env: &mut VariableEnv,
     statement: Statement,
+    return_type: Option<&Type>,
 ) -> Result<TaggedStatement> {
     match statement {
         Statement::ExternFunctionDeclaration(
+            _docs,
             func_name,
             parameters,
             return_type_name,
@@ -221,13 +325,13 @@ fn translate_statement(
             parameter_types.push(return_type.clone());
             variable_env.insert(
                 func_name.clone(),
-                Type::PolymorphicType(
+                Binding::item(Type::PolymorphicType(
                     Vec::new(),
                     Box::new(Type::Apply(
                         TypeConstructor::Arrow,
                         parameter_types,
                     )),
-                ),
+                )),
             );
             Ok(TaggedStatement::ExternFunctionDeclaration(
                 FunctionDeclaration {
@@ -241,6 +345,7 @@ fn translate_statement(
             ))
         }
         Statement::StructDeclaration(
+            _docs,
             struct_name,
             maybe_formal_arguments,
             fields,
@@ -277,11 +382,54 @@ fn translate_statement(
             );
 
             type_env.insert(struct_name.clone(), new_type.clone());
-            variable_env.insert(struct_name, new_type);
+            variable_env.insert(struct_name, Binding::item(new_type));
 
             Ok(TaggedStatement::StructDeclaration)
         }
+        Statement::ConstDeclaration(_docs, name, type_name, expression) => {
+            let (type_, value) = translate_global_initializer(
+                type_env,
+                variable_env,
+                &name,
+                *type_name,
+                *expression,
+            )?;
+            variable_env.insert(
+                name.value,
+                Binding {
+                    value: Some(value),
+                    ..Binding::variable(type_, Mutability::Immutable, name.span)
+                },
+            );
+            Ok(TaggedStatement::ConstDeclaration)
+        }
+        Statement::StaticDeclaration(
+            _docs,
+            mutability,
+            name,
+            type_name,
+            expression,
+        ) => {
+            let (type_, value) = translate_global_initializer(
+                type_env,
+                variable_env,
+                &name,
+                *type_name,
+                *expression,
+            )?;
+            variable_env.insert(
+                name.value.clone(),
+                Binding {
+                    is_static: true,
+                    ..Binding::variable(type_.clone(), mutability, name.span)
+                },
+            );
+            Ok(TaggedStatement::StaticDeclaration(
+                name.value, mutability, type_, value,
+            ))
+        }
         Statement::FunctionDeclaration(
+            _docs,
             func_name,
             maybe_formal_arguments,
             parameters,
@@ -295,14 +443,23 @@ fn translate_statement(
                         .insert(ty.clone(), Type::Variable(ty.clone()));
                 });
             };
+            // Parameters and local variables are only visible in the function
+            let mut local_variable_env = variable_env.clone();
             let function_decl_types = parameters
                 .iter()
-                .map(|(param_name, type_name)| {
+                .map(|(mutability, param_name, type_name)| {
                     let type_ = translate_type_identifier(
                         &mut local_type_env,
                         type_name.as_ref().clone(),
                     )?;
-                    variable_env.insert(param_name.clone(), type_.clone());
+                    local_variable_env.insert(
+                        param_name.value.clone(),
+                        Binding::variable(
+                            type_.clone(),
+                            *mutability,
+                            param_name.span.clone(),
+                        ),
+                    );
                     Ok(type_)
                 })
                 .collect::<Result<Vec<Type>>>()?;
@@ -321,34 +478,38 @@ fn translate_statement(
             )?;
             parameter_types.push(return_type.clone());
 
-            variable_env.insert(
-                func_name.clone(),
-                Type::PolymorphicType(
-                    maybe_formal_arguments.unwrap_or_default(),
-                    Box::new(Type::Apply(
-                        TypeConstructor::Arrow,
-                        parameter_types.clone(),
-                    )),
-                ),
-            );
+            let function_binding = Binding::item(Type::PolymorphicType(
+                maybe_formal_arguments.unwrap_or_default(),
+                Box::new(Type::Apply(
+                    TypeConstructor::Arrow,
+                    parameter_types.clone(),
+                )),
+            ));
+            variable_env
+                .insert(func_name.value.clone(), function_binding.clone());
+            local_variable_env
+                .insert(func_name.value.clone(), function_binding);
             let tagged_statements = func_body
                 .iter()
                 .map(|stmt| {
                     translate_statement(
                         &mut local_type_env,
-                        variable_env,
+                        &mut local_variable_env,
                         stmt.as_ref().clone(),
+                        Some(&return_type),
                     )
                 })
                 .collect::<Result<Vec<_>>>()?;
             let params = parameter_types
                 .iter()
                 .zip(parameters)
-                .map(|(type_, (param_name, _))| (param_name, type_.clone()))
+                .map(|(type_, (_, param_name, _))| {
+                    (param_name.value, type_.clone())
+                })
                 .collect::<Vec<_>>();
             Ok(TaggedStatement::FunctionDeclaration(
                 FunctionDeclaration {
-                    name: func_name,
+                    name: func_name.value,
                     params,
                     return_type,
                 },
@@ -356,22 +517,58 @@ fn translate_statement(
             ))
         }
         Statement::Expression(expression) => {
-            Ok(TaggedStatement::Expression(translate_expression(
-                type_env,
-                variable_env,
-                expression.as_ref().clone(),
-            )?))
+            let tagged_expression = match *expression {
+                Expression::Variable(pattern, annotation, expr) => {
+                    translate_let(
+                        type_env,
+                        variable_env,
+                        pattern,
+                        annotation,
+                        *expr,
+                    )
+                }
+                expression => {
+                    translate_expression(type_env, variable_env, expression)
+                }
+            }?;
+            Ok(TaggedStatement::Expression(tagged_expression))
         }
-        Statement::Return(maybe_expression) => {
-            if let Some(expr) = maybe_expression {
-                Ok(TaggedStatement::Return(Some(translate_expression(
-                    type_env,
-                    variable_env,
-                    expr.as_ref().clone(),
-                )?)))
-            } else {
-                Ok(TaggedStatement::Return(None))
+        Statement::Return(span, maybe_expression) => {
+            let tagged_return = maybe_expression
+                .map(|expr| match return_type {
+                    Some(return_type) => {
+                        translate_expression_with_expected_type(
+                            type_env,
+                            variable_env,
+                            *expr,
+                            return_type,
+                        )
+                    }
+                    None => translate_expression(type_env, variable_env, *expr),
+                })
+                .transpose()?;
+            if let Some(return_type) = return_type {
+                let found = tagged_return.clone().map_or_else(
+                    || Type::Apply(TypeConstructor::Void, Vec::new()),
+                    Type::from,
+                );
+                unify(return_type.clone(), found.clone()).map_err(|_| {
+                    RoxError::with_file_placeholder("Mismatched types")
+                        .with_label(
+                            &format!("this returns {:?}", found),
+                            tagged_return
+                                .as_ref()
+                                .and_then(TaggedExpression::span)
+                                .unwrap_or(&span)
+                                .clone(),
+                        )
+                        .with_note(&format!(
+                            "the function is declared to return {:?}",
+                            return_type
+                        ))
+                })?;
             }
+            Ok(TaggedStatement::Return(tagged_return))
         }
         Statement::IfElse(if_expression, body, maybe_else_block) => {
             let tagged_if = Box::new(translate_expression(
@@ -390,6 +587,7 @@ fn translate_statement(
                         type_env,
                         variable_env,
                         s.as_ref().clone(),
+                        return_type,
                     )
                 })
                 .collect::<Result<Vec<_>>>()?;
@@ -401,6 +599,7 @@ fn translate_statement(
                             type_env,
                             variable_env,
                             s.as_ref().clone(),
+                            return_type,
                         )
                     })
                     .collect::<Result<Vec<_>>>()?;
@@ -416,16 +615,209 @@ fn translate_statement(
     }
 }
 
+/// Type checks the initializer of a `const` or `static` against its
+/// annotation, and evaluates it to a literal
+fn translate_global_initializer(
+    type_env: &mut TypeEnv,
+    variable_env: &mut VariableEnv,
+    name: &Spanned<Identifier>,
+    type_name: TypeName,
+    expression: Expression,
+) -> Result<(Type, TaggedExpression)> {
+    let type_ = translate_type_identifier(type_env, type_name)?;
+    let tagged_expression = translate_expression_with_expected_type(
+        type_env,
+        variable_env,
+        expression,
+        &type_,
+    )?;
+    unify(type_.clone(), tagged_expression.clone().into()).map_err(|_| {
+        RoxError::with_file_placeholder("Mismatched types")
+            .with_label(
+                "the initializer doesn't have the declared type",
+                name.span.clone(),
+            )
+            .with_note(&format!(
+                "expected {:?}, found {:?}",
+                type_,
+                Type::from(tagged_expression.clone())
+            ))
+    })?;
+    let value = evaluate(&tagged_expression)?.ok_or_else(|| {
+        RoxError::with_file_placeholder(
+            format!(
+                "The value of `{}` isn't known at compile time",
+                name.value
+            )
+            .as_ref(),
+        )
+        .with_label("declared here", name.span.clone())
+        .with_note(
+            "constants and statics must be initialized with literals, \
+             other constants, or operations on them",
+        )
+    })?;
+    Ok((type_, value))
+}
+
+/// Evaluates the length of a `[value; length]` array, which must be a
+/// non-negative integer known at compile time
+fn translate_array_length(
+    type_env: &mut TypeEnv,
+    variable_env: &mut VariableEnv,
+    length: Spanned<Box<Expression>>,
+) -> Result<usize> {
+    let Spanned {
+        value: length,
+        span,
+    } = length;
+    let tagged_length = translate_expression(type_env, variable_env, *length)?;
+    let invalid_length = |message: &str| {
+        RoxError::with_file_placeholder("Invalid array length")
+            .with_label(message, span.clone())
+    };
+    match evaluate(&tagged_length)? {
+        Some(TaggedExpression::Int(int, type_)) => {
+            let is_negative = match type_.without_empty_quantifier() {
+                Type::Apply(constructor, _) if constructor.is_signed() => {
+                    int.value > constructor.max_int().unwrap()
+                }
+                _ => false,
+            };
+            if is_negative {
+                Err(invalid_length("array lengths can't be negative"))
+            } else {
+                Ok(int.value as usize)
+            }
+        }
+        Some(_) => Err(invalid_length("expected an integer")),
+        None => Err(invalid_length("this isn't known at compile time")
+            .with_note(
+            "array lengths must be literals, constants, or operations on them",
+        )),
+    }
+}
+
+/// The variable that an assignment writes into, such as `xs` in
+/// `xs[0].name = ...`, or `None` for temporaries like function results
+fn assigned_variable(lval: &Expression) -> Option<&Spanned<Identifier>> {
+    match lval {
+        Expression::Identifier(name) => Some(name),
+        Expression::BracketAccess(inner, _)
+        | Expression::DotAccess(inner, _)
+        | Expression::TupleAccess(inner, _) => assigned_variable(inner),
+        _ => None,
+    }
+}
+
 fn translate_lvalue(
     type_env: &mut TypeEnv,
     variable_env: &mut VariableEnv,
     lval: LValue,
 ) -> Result<TaggedLValue> {
-    Ok(TaggedLValue(translate_expression(
-        type_env,
-        variable_env,
-        lval.0,
-    )?))
+    if let Some(name) = assigned_variable(&lval.0) {
+        if let Some(binding) = variable_env.get(&name.value) {
+            check_assignable(name, binding)?;
+        }
+    }
+    let tagged_lval = translate_expression(type_env, variable_env, lval.0)?;
+    if let TaggedExpression::BracketAccess(string, ..) = &tagged_lval {
+        if let Type::Apply(TypeConstructor::String, _) =
+            Type::from(string.as_ref().clone())
+        {
+            return Err(RoxError::with_file_placeholder(
+                "Strings are immutable and cannot be assigned to by index",
+            ));
+        }
+    }
+    Ok(TaggedLValue(tagged_lval))
+}
+
+/// Reports assignments to constants and to variables declared without `mut`
+fn check_assignable(
+    name: &Spanned<Identifier>,
+    binding: &Binding,
+) -> Result<()> {
+    if binding.mutability == Mutability::Mutable {
+        return Ok(());
+    }
+    let is_const = binding.value.is_some();
+    let message = if is_const {
+        format!("Cannot assign to constant `{}`", name.value)
+    } else {
+        format!("Cannot assign to immutable variable `{}`", name.value)
+    };
+    let mut error = RoxError::with_file_placeholder(&message)
+        .with_label("cannot assign to this", name.span.clone());
+    if let Some(declaration) = &binding.declaration {
+        error = error.with_secondary_label(
+            &format!("`{}` is declared here", name.value),
+            declaration.clone(),
+        );
+        if !is_const {
+            let keywords = if binding.is_static {
+                "static mut"
+            } else {
+                "mut"
+            };
+            error = error.with_note(&format!(
+                "help: declare it as `{} {}` to make it mutable",
+                keywords, name.value
+            ));
+        }
+    }
+    Err(error)
+}
+
+/// Type checks a `let`, which binds variables but isn't a value
+fn translate_let(
+    type_env: &mut TypeEnv,
+    variable_env: &mut VariableEnv,
+    pattern: Pattern,
+    annotation: Option<Spanned<Box<TypeName>>>,
+    expr: Expression,
+) -> Result<TaggedExpression> {
+    let (expr_value, variable_type) = match annotation {
+        Some(annotation) => {
+            let expected_type = translate_type_identifier(
+                type_env,
+                annotation.value.as_ref().clone(),
+            )?;
+            let expr_value = translate_expression_with_expected_type(
+                type_env,
+                variable_env,
+                expr,
+                &expected_type,
+            )?;
+            unify(expected_type.clone(), expr_value.clone().into()).map_err(
+                |_| {
+                    RoxError::with_file_placeholder("Mismatched types")
+                        .with_label(
+                            "the initializer doesn't have this type",
+                            annotation.span.clone(),
+                        )
+                        .with_note(&format!(
+                            "expected {:?}, found {:?}",
+                            expected_type,
+                            Type::from(expr_value.clone())
+                        ))
+                },
+            )?;
+            (expr_value, expected_type)
+        }
+        None => {
+            let expr_value =
+                translate_expression(type_env, variable_env, expr)?;
+            let variable_type = expr_value.clone().into();
+            (expr_value, variable_type)
+        }
+    };
+    bind_pattern(variable_env, &pattern, variable_type.clone())?;
+    Ok(TaggedExpression::Variable(
+        pattern,
+        Box::new(expr_value),
+        Box::new(variable_type),
+    ))
 }
 
 fn translate_expression(
@@ -433,8 +825,117 @@ fn translate_expression(
     variable_env: &mut VariableEnv,
     expression: Expression,
 ) -> Result<TaggedExpression> {
+    if let Some(span) = expression.span() {
+        record_span(span);
+    }
     match expression {
-        Expression::DotAccess(..) => todo!(),
+        Expression::DotAccess(struct_expr, field_name) => {
+            let tagged_struct = translate_expression(
+                type_env,
+                variable_env,
+                struct_expr.as_ref().clone(),
+            )?;
+            let struct_type = expand(
+                Type::from(tagged_struct.clone())
+                    .without_empty_quantifier()
+                    .clone(),
+            );
+            if let Type::Apply(TypeConstructor::Record(fields), ..) =
+                struct_type
+            {
+                let (_, field_type) = fields
+                    .iter()
+                    .find(|(name, _)| *name == field_name)
+                    .ok_or_else(|| {
+                        RoxError::with_file_placeholder(
+                            format!("Struct has no field named {}", field_name)
+                                .as_ref(),
+                        )
+                    })?;
+                Ok(TaggedExpression::DotAccess(
+                    Box::new(tagged_struct),
+                    field_name,
+                    Box::new(field_type.clone()),
+                ))
+            } else {
+                Err(RoxError::with_file_placeholder(
+                    format!(
+                        "Cannot access field {} of non-struct type",
+                        field_name
+                    )
+                    .as_ref(),
+                ))
+            }
+        }
+        Expression::TupleAccess(tuple_expr, index) => {
+            let tagged_tuple = translate_expression(
+                type_env,
+                variable_env,
+                tuple_expr.as_ref().clone(),
+            )?;
+            let tuple_type = expand(
+                Type::from(tagged_tuple.clone())
+                    .without_empty_quantifier()
+                    .clone(),
+            );
+            let elements =
+                tuple_type.get_tuple_elements().ok_or_else(|| {
+                    RoxError::with_file_placeholder(
+                        format!(
+                            "Cannot access element {} of non-tuple type",
+                            index.value
+                        )
+                        .as_ref(),
+                    )
+                })?;
+            let element_type =
+                elements.get(index.value).cloned().ok_or_else(|| {
+                    RoxError::with_file_placeholder(
+                        format!(
+                            "Tuple has no element {}, it only has {} elements",
+                            index.value,
+                            elements.len()
+                        )
+                        .as_ref(),
+                    )
+                })?;
+            Ok(TaggedExpression::TupleAccess(
+                Box::new(tagged_tuple),
+                index,
+                Box::new(element_type),
+            ))
+        }
+        Expression::RepeatedArray(value, length) => {
+            let tagged_value =
+                translate_expression(type_env, variable_env, *value)?;
+            let length =
+                translate_array_length(type_env, variable_env, length)?;
+            Ok(TaggedExpression::RepeatedArray(
+                Box::new(tagged_value.clone()),
+                length,
+                Box::new(Type::Apply(
+                    TypeConstructor::Array(Box::new(tagged_value.into())),
+                    Vec::new(),
+                )),
+            ))
+        }
+        Expression::Tuple(elements) => {
+            let tagged_elements = elements
+                .into_iter()
+                .map(|element| {
+                    translate_expression(type_env, variable_env, *element)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let element_types =
+                tagged_elements.iter().cloned().map(Type::from).collect();
+            Ok(TaggedExpression::Tuple(
+                tagged_elements,
+                Box::new(Type::Apply(
+                    TypeConstructor::Tuple(element_types),
+                    Vec::new(),
+                )),
+            ))
+        }
         Expression::BracketAccess(array_expr, index_expr) => {
             let tagged_left = translate_expression(
                 type_env,
@@ -451,18 +952,25 @@ fn translate_expression(
                 Type::Apply(TypeConstructor::Int, Vec::new()),
             )?;
 
-            if let Type::Apply(TypeConstructor::Array(inner_type, ..), ..) =
-                Type::from(tagged_left.clone())
-            {
-                Ok(TaggedExpression::BracketAccess(
-                    Box::new(tagged_left),
-                    Box::new(tagged_right),
-                    inner_type,
-                ))
-            } else {
-                Err(RoxError::with_file_placeholder(
+            match Type::from(tagged_left.clone()) {
+                Type::Apply(TypeConstructor::Array(inner_type, ..), ..) => {
+                    Ok(TaggedExpression::BracketAccess(
+                        Box::new(tagged_left),
+                        Box::new(tagged_right),
+                        inner_type,
+                    ))
+                }
+                // Indexing into a string gives back a single character string
+                string_type @ Type::Apply(TypeConstructor::String, ..) => {
+                    Ok(TaggedExpression::BracketAccess(
+                        Box::new(tagged_left),
+                        Box::new(tagged_right),
+                        Box::new(string_type),
+                    ))
+                }
+                _ => Err(RoxError::with_file_placeholder(
                     "Cannot index into non-array type",
-                ))
+                )),
             }
         }
         Expression::Or(left_expr, right_expr) => {
@@ -481,7 +989,7 @@ fn translate_expression(
                 Type::Apply(TypeConstructor::Bool, Vec::new()),
             )?;
             unify(tagged_right.clone().into(), tagged_left.clone().into())?;
-            Ok(TaggedExpression::Or(
+            fold(TaggedExpression::Or(
                 Box::new(tagged_left),
                 Box::new(tagged_right),
             ))
@@ -502,15 +1010,18 @@ fn translate_expression(
                 Type::Apply(TypeConstructor::Bool, Vec::new()),
             )?;
             unify(tagged_right.clone().into(), tagged_left.clone().into())?;
-            Ok(TaggedExpression::And(
+            fold(TaggedExpression::And(
                 Box::new(tagged_left),
                 Box::new(tagged_right),
             ))
         }
         Expression::Array(expressions) => {
-            // TODO: Handle starting with an empty array
-            let first_expression =
-                expressions.first().expect("Cannot initialize empty array");
+            let first_expression = expressions.first().ok_or_else(|| {
+                RoxError::with_file_placeholder(
+                    "Cannot infer the type of an empty array",
+                )
+                .with_note("add a type annotation, as in `let xs: [Int] = [];`")
+            })?;
             let first_tagged_expression = translate_expression(
                 type_env,
                 variable_env,
@@ -568,102 +1079,124 @@ fn translate_expression(
                     translate_type_identifier(type_env, i.as_ref().clone())
                 })
                 .collect::<Result<Vec<_>>>()?;
-            let tagged_function_identifier = translate_expression(
+            translate_function_call(
                 type_env,
                 variable_env,
-                Expression::Identifier(ident.clone()),
-            )?;
-            let tagged_argument_expressions = args
+                ident,
+                instantiated_generics,
+                args,
+            )
+        }
+        Expression::Identifier(x) => {
+            let binding = variable_env.get(&x.value).ok_or_else(|| {
+                RoxError::with_file_placeholder(
+                    format!("Encountered unknown identifier: {}", x.value)
+                        .as_ref(),
+                )
+            })?;
+            match &binding.value {
+                Some(value) => Ok(value.clone()),
+                None => Ok(TaggedExpression::Identifier(
+                    x,
+                    Box::new(binding.type_.clone()),
+                )),
+            }
+        }
+        Expression::String(s) => Ok(TaggedExpression::String(s)),
+        Expression::Char(c) => Ok(TaggedExpression::Char(c)),
+        Expression::InterpolatedString(parts) => {
+            let tagged_parts = parts
                 .iter()
-                .map(|a| {
-                    translate_expression(
+                .map(|part| {
+                    let span = part.span().cloned();
+                    let tagged_part = translate_expression(
                         type_env,
                         variable_env,
-                        a.as_ref().clone(),
-                    )
+                        part.as_ref().clone(),
+                    )?;
+                    match Type::from(tagged_part.clone()) {
+                        Type::Apply(TypeConstructor::String, _)
+                        | Type::Apply(TypeConstructor::Bool, _)
+                        | Type::Apply(TypeConstructor::Char, _) => {
+                            Ok(tagged_part)
+                        }
+                        Type::Apply(constructor, _)
+                            if constructor.is_integer()
+                                || constructor.is_float() =>
+                        {
+                            Ok(tagged_part)
+                        }
+                        other => {
+                            let error = RoxError::with_file_placeholder(
+                                format!(
+                                    "Cannot interpolate value of type {:?} into a string",
+                                    other
+                                )
+                                .as_ref(),
+                            );
+                            Err(match span {
+                                Some(span) => error.with_label(
+                                    "cannot be interpolated",
+                                    span,
+                                ),
+                                None => error,
+                            })
+                        }
+                    }
                 })
                 .collect::<Result<Vec<_>>>()?;
-            if let Type::PolymorphicType(generics, func_type_constructor) =
-                expand(tagged_function_identifier.into())
-            {
-                let mut all_types: TypeEnv = type_env
-                    .iter_mut()
-                    .map(|(n, t)| (n.clone(), t.clone()))
-                    .collect();
-                generics
-                    .iter()
-                    .cloned()
-                    .zip(instantiated_generics)
-                    .for_each(|(ident, type_)| {
-                        all_types.insert(ident, type_);
-                    });
-
-                if let Type::Apply(_constructor, mut types) =
-                    *func_type_constructor
-                {
-                    let return_type = types.pop().unwrap();
-                    types
-                        .iter()
-                        .zip(tagged_argument_expressions.clone())
-                        .map(|(t, expr)| {
-                            unify(
-                                expr.into(),
-                                substitute(t.clone(), &mut all_types),
-                            )
-                        })
-                        .collect::<Result<Vec<_>>>()?;
-                    let function_return_type =
-                        substitute(return_type, &mut all_types);
-                    Ok(TaggedExpression::FunctionCall(
-                        ident,
-                        tagged_argument_expressions,
-                        Box::new(function_return_type),
-                    ))
-                } else {
-                    todo!("This is an error but I haven't made it actually useful, sorry!")
-                }
-            } else {
-                todo!("This is an error but I haven't made it actually useful, sorry!")
-            }
+            Ok(TaggedExpression::InterpolatedString(tagged_parts))
         }
-        Expression::Identifier(x) => Ok(TaggedExpression::Identifier(
-            x.clone(),
-            Box::new(
-                variable_env
-                    .get(&x.value)
-                    .ok_or_else(|| {
-                        RoxError::with_file_placeholder(
-                            format!(
-                                "Encountered unknown identifier: {}",
-                                x.value
-                            )
-                            .as_ref(),
-                        )
-                    })?
-                    .clone(),
-            ),
-        )),
-        Expression::String(s) => Ok(TaggedExpression::String(s)),
         Expression::Operation(left, operation, right) => {
-            let tagged_left = translate_expression(
-                type_env,
-                variable_env,
-                left.as_ref().clone(),
-            )?;
-            let tagged_right = translate_expression(
-                type_env,
-                variable_env,
-                right.as_ref().clone(),
-            )?;
-            Ok(TaggedExpression::Operation(
+            // An unsuffixed literal takes the type of the other operand
+            let (tagged_left, tagged_right) = if is_unsuffixed_literal(&left)
+                && !is_unsuffixed_literal(&right)
+            {
+                let tagged_right =
+                    translate_expression(type_env, variable_env, *right)?;
+                let tagged_left = translate_expression_with_expected_type(
+                    type_env,
+                    variable_env,
+                    *left,
+                    &tagged_right.clone().into(),
+                )?;
+                (tagged_left, tagged_right)
+            } else {
+                let tagged_left =
+                    translate_expression(type_env, variable_env, *left)?;
+                let tagged_right = if is_unsuffixed_literal(&right) {
+                    translate_expression_with_expected_type(
+                        type_env,
+                        variable_env,
+                        *right,
+                        &tagged_left.clone().into(),
+                    )
+                } else {
+                    translate_expression(type_env, variable_env, *right)
+                }?;
+                (tagged_left, tagged_right)
+            };
+            unify(tagged_left.clone().into(), tagged_right.clone().into())?;
+            check_operand_type(&operation.value, tagged_left.clone().into())?;
+            fold(TaggedExpression::Operation(
                 Box::new(tagged_left.clone()),
                 operation,
                 Box::new(tagged_right),
                 Box::new(tagged_left.into()),
             ))
         }
-        Expression::Float(n) => Ok(TaggedExpression::Float(n)),
-        Expression::Int(n) => Ok(TaggedExpression::Int(n)),
+        Expression::Float(n, suffix) => Ok(TaggedExpression::Float(
+            n,
+            Box::new(Type::Apply(
+                numeric_literal_type(suffix, TypeConstructor::Float),
+                Vec::new(),
+            )),
+        )),
+        Expression::Int(n, suffix) => int_literal(
+            n,
+            numeric_literal_type(suffix, TypeConstructor::Int),
+            None,
+        ),
         Expression::StructInstantiation(
             identifier,
             maybe_generic_args,
@@ -688,12 +1221,20 @@ fn translate_expression(
                 Expression::Identifier(identifier.clone()),
             )?;
 
-            let struct_type = type_env.get(&identifier.value).unwrap();
+            let not_a_struct = || {
+                RoxError::with_file_placeholder(&format!(
+                    "`{}` is not a struct",
+                    identifier.value
+                ))
+                .with_label("not a struct", identifier.span.clone())
+            };
 
             if let Type::PolymorphicType(generics, record_type_constructor) =
                 expand(tagged_struct_identifier.into())
             {
-                let fields = record_type_constructor.get_record_fields();
+                let fields = record_type_constructor
+                    .get_record_fields()
+                    .ok_or_else(not_a_struct)?;
                 let mut all_types: TypeEnv = type_env
                     .iter_mut()
                     .map(|(n, t)| (n.clone(), t.clone()))
@@ -712,7 +1253,16 @@ fn translate_expression(
                             .find(|(f, _): &&(String, Box<Expression>)| {
                                 f == field_name
                             })
-                            .unwrap();
+                            .ok_or_else(|| {
+                                RoxError::with_file_placeholder(&format!(
+                                    "Missing field `{}` in `{}`",
+                                    field_name, identifier.value
+                                ))
+                                .with_label(
+                                    &format!("missing `{}`", field_name),
+                                    identifier.span.clone(),
+                                )
+                            })?;
                         let tagged_expression = translate_expression(
                             type_env,
                             variable_env,
@@ -741,43 +1291,89 @@ fn translate_expression(
                     tagged_field_params,
                 ))
             } else {
-                unimplemented!("{:?}", struct_type)
+                Err(not_a_struct())
+            }
+        }
+        Expression::Variable(pattern, _, _) => {
+            if let Some(span) = pattern.span() {
+                record_span(span);
             }
+            Err(not_yet_supported("Using a `let` as a value"))
         }
-        Expression::Variable(ident, expr) => {
-            let expr_value = translate_expression(
+        Expression::Cast(expr, type_name) => {
+            let tagged_expression = translate_expression(
                 type_env,
                 variable_env,
                 expr.as_ref().clone(),
             )?;
-            variable_env.insert(ident.value.clone(), expr_value.clone().into());
-            Ok(TaggedExpression::Variable(
-                ident,
-                Box::new(expr_value.clone()),
-                Box::new(expr_value.into()),
+            let target_type =
+                type_env.get(&type_name.value).cloned().ok_or_else(|| {
+                    RoxError::with_file_placeholder(
+                        format!("Unknown type `{}`", type_name.value).as_ref(),
+                    )
+                })?;
+            check_cast(tagged_expression.clone().into(), &target_type)?;
+            fold(TaggedExpression::Cast(
+                Box::new(tagged_expression),
+                Box::new(target_type),
+                type_name.span,
             ))
         }
         Expression::Unary(unary, expr) => {
+            if let (Unary::Negate, Expression::Int(n, suffix)) =
+                (&unary.value, expr.as_ref())
+            {
+                let constructor =
+                    numeric_literal_type(*suffix, TypeConstructor::Int);
+                if constructor.is_signed() {
+                    return int_literal(
+                        n.clone(),
+                        constructor,
+                        Some(&unary.span),
+                    );
+                }
+            }
             let tagged_expression = translate_expression(
                 type_env,
                 variable_env,
                 expr.as_ref().clone(),
             )?;
-            match unary {
+            match unary.value {
                 Unary::Not => {
                     unify(
                         tagged_expression.clone().into(),
                         Type::Apply(TypeConstructor::Bool, Vec::new()),
                     )?;
                 }
-                Unary::Negate => {
-                    unify(
-                        tagged_expression.clone().into(),
-                        Type::Apply(TypeConstructor::Float, Vec::new()),
-                    )?;
-                }
+                Unary::Negate => match tagged_expression.clone().into() {
+                    Type::Apply(constructor, _)
+                        if constructor.is_signed()
+                            || constructor.is_float() => {}
+                    operand_type => {
+                        return Err(RoxError::with_file_placeholder(
+                            format!(
+                                "Operation {:?} is not supported for type {:?}",
+                                unary.value, operand_type
+                            )
+                            .as_ref(),
+                        ))
+                    }
+                },
+                Unary::BitwiseNot => match tagged_expression.clone().into() {
+                    Type::Apply(constructor, _) if constructor.is_integer() => {
+                    }
+                    operand_type => {
+                        return Err(RoxError::with_file_placeholder(
+                            format!(
+                                "Operation {:?} is not supported for type {:?}",
+                                unary.value, operand_type
+                            )
+                            .as_ref(),
+                        ))
+                    }
+                },
             }
-            Ok(TaggedExpression::Unary(
+            fold(TaggedExpression::Unary(
                 unary,
                 Box::new(tagged_expression.clone()),
                 Box::new(tagged_expression.into()),
@@ -787,13 +1383,483 @@ fn translate_expression(
     }
 }
 
+/// Gets the type of a numeric literal from its suffix, if it has one
+fn numeric_literal_type(
+    suffix: Option<NumericSuffix>,
+    default: TypeConstructor,
+) -> TypeConstructor {
+    use NumericSuffix::*;
+    match suffix {
+        None => default,
+        Some(I8) => TypeConstructor::Int8,
+        Some(I16) => TypeConstructor::Int16,
+        Some(I32) => TypeConstructor::Int,
+        Some(I64) => TypeConstructor::Int64,
+        Some(U8) => TypeConstructor::UInt8,
+        Some(U16) => TypeConstructor::UInt16,
+        Some(U32) => TypeConstructor::UInt32,
+        Some(U64) => TypeConstructor::UInt64,
+        Some(F32) => TypeConstructor::Float32,
+        Some(F64) => TypeConstructor::Float,
+    }
+}
+
+/// Types an integer literal and checks that it fits in its type.
+/// A literal that's directly negated, as in `-128i8`, is checked as a
+/// negative number, so that the minimum value of a type can be written.
+/// `negation` is the span of its `-`.
+fn int_literal(
+    int: Spanned<u64>,
+    constructor: TypeConstructor,
+    negation: Option<&Span>,
+) -> Result<TaggedExpression> {
+    let max = constructor.max_int().unwrap();
+    let (value, span, fits) = match negation {
+        // The minimum of a signed type is one further from zero than its maximum
+        Some(negation) => (
+            int.value.wrapping_neg(),
+            Span(negation.0, int.span.1),
+            int.value <= max + 1,
+        ),
+        None => (int.value, int.span, int.value <= max),
+    };
+    if !fits {
+        return Err(RoxError::with_file_placeholder(
+            "Integer literal out of range",
+        )
+        .with_label(&format!("this doesn't fit in {:?}", constructor), span));
+    }
+    Ok(TaggedExpression::Int(
+        Spanned { value, span },
+        Box::new(Type::Apply(constructor, Vec::new())),
+    ))
+}
+
+/// Whether an expression is a number literal without a suffix, which
+/// takes its type from the operand or parameter that it's used with
+fn is_unsuffixed_literal(expression: &Expression) -> bool {
+    match expression {
+        Expression::Int(_, None) | Expression::Float(_, None) => true,
+        Expression::Unary(unary, operand) => {
+            matches!(unary.value, Unary::Negate)
+                && matches!(operand.as_ref(), Expression::Int(_, None))
+        }
+        _ => false,
+    }
+}
+
+/// Checks that the operator is defined for the given operand type
+fn check_operand_type(operation: &Operation, operand_type: Type) -> Result<()> {
+    use Operation::*;
+    let is_bitwise = matches!(
+        operation,
+        BitwiseAnd | BitwiseOr | BitwiseXor | ShiftLeft | ShiftRight
+    );
+    let is_supported = match &operand_type {
+        Type::Apply(constructor, _) if constructor.is_integer() => true,
+        Type::Apply(constructor, _) if constructor.is_float() => !is_bitwise,
+        Type::Apply(TypeConstructor::Bool, _) => {
+            matches!(operation, Equals | NotEquals)
+        }
+        Type::Apply(TypeConstructor::Char, _) => {
+            matches!(operation, Equals | NotEquals | GreaterThan | LessThan)
+        }
+        Type::Apply(TypeConstructor::String, _) => {
+            matches!(
+                operation,
+                Add | Equals | NotEquals | GreaterThan | LessThan
+            )
+        }
+        _ => false,
+    };
+    if is_supported {
+        Ok(())
+    } else {
+        Err(RoxError::with_file_placeholder(
+            format!(
+                "Operation {:?} is not supported for type {:?}",
+                operation, operand_type
+            )
+            .as_ref(),
+        ))
+    }
+}
+
+/// Checks that a value can be converted to the target type with `as`.
+///
+/// Any number can be cast to any other number, `Char`s can be cast
+/// to and from integers and `Bool`s can be cast to integers.
+fn check_cast(from: Type, to: &Type) -> Result<()> {
+    use TypeConstructor::*;
+    let is_number = |constructor: &TypeConstructor| {
+        constructor.is_integer() || constructor.is_float()
+    };
+    let is_valid = match (&from, to) {
+        (Type::Apply(from, _), Type::Apply(to, _)) => match (from, to) {
+            (from, to) if from == to => true,
+            (from, to) if is_number(from) && is_number(to) => true,
+            (Char, other) | (other, Char) => other.is_integer(),
+            (Bool, to) => to.is_integer(),
+            _ => false,
+        },
+        _ => false,
+    };
+    if is_valid {
+        Ok(())
+    } else {
+        Err(RoxError::with_file_placeholder(
+            format!("Cannot cast a value of type {:?} to {:?}", from, to)
+                .as_ref(),
+        ))
+    }
+}
+
+fn translate_function_call(
+    type_env: &mut TypeEnv,
+    variable_env: &mut VariableEnv,
+    ident: Spanned<Identifier>,
+    instantiated_generics: Vec<Type>,
+    args: Vec<Box<Expression>>,
+) -> Result<TaggedExpression> {
+    if !variable_env.contains_key(&ident.value) {
+        return Err(undefined_function(&ident));
+    }
+    let tagged_function_identifier = translate_expression(
+        type_env,
+        variable_env,
+        Expression::Identifier(ident.clone()),
+    )?;
+    if let Type::PolymorphicType(generics, func_type_constructor) =
+        expand(tagged_function_identifier.into())
+    {
+        let mut all_types: TypeEnv = type_env
+            .iter_mut()
+            .map(|(n, t)| (n.clone(), t.clone()))
+            .collect();
+        generics
+            .iter()
+            .cloned()
+            .zip(instantiated_generics)
+            .for_each(|(ident, type_)| {
+                all_types.insert(ident, type_);
+            });
+
+        if let Type::Apply(_constructor, mut types) = *func_type_constructor {
+            let return_type = types.pop().unwrap();
+            if args.len() != types.len() {
+                return Err(wrong_argument_count(
+                    &ident,
+                    types.len(),
+                    args.len(),
+                ));
+            }
+            let tagged_argument_expressions = types
+                .into_iter()
+                .zip(args)
+                .map(|(t, arg)| {
+                    let parameter_type = substitute(t, &mut all_types);
+                    // Unsuffixed literals take the type of the parameter
+                    let tagged_argument = if is_unsuffixed_literal(&arg) {
+                        translate_expression_with_expected_type(
+                            type_env,
+                            variable_env,
+                            *arg,
+                            &parameter_type,
+                        )
+                    } else {
+                        translate_expression(type_env, variable_env, *arg)
+                    }?;
+                    unify(tagged_argument.clone().into(), parameter_type)?;
+                    Ok(tagged_argument)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let function_return_type = substitute(return_type, &mut all_types);
+            Ok(TaggedExpression::FunctionCall(
+                ident,
+                tagged_argument_expressions,
+                Box::new(function_return_type),
+            ))
+        } else {
+            Err(not_a_function(&ident))
+        }
+    } else {
+        Err(not_a_function(&ident))
+    }
+}
+
+/// The error for calling a name that isn't in scope, which includes
+/// functions that are declared after the call
+fn undefined_function(ident: &Spanned<Identifier>) -> RoxError {
+    RoxError::with_file_placeholder(&format!(
+        "Cannot find function `{}`",
+        ident.value
+    ))
+    .with_label("not found in this scope", ident.span.clone())
+    .with_note("functions must be declared before they're called")
+}
+
+fn wrong_argument_count(
+    ident: &Spanned<Identifier>,
+    expected: usize,
+    found: usize,
+) -> RoxError {
+    let arguments = |count: usize| {
+        format!("{} argument{}", count, if count == 1 { "" } else { "s" })
+    };
+    RoxError::with_file_placeholder(&format!(
+        "`{}` takes {}",
+        ident.value,
+        arguments(expected)
+    ))
+    .with_label(
+        &format!("expected {}, found {}", arguments(expected), found),
+        ident.span.clone(),
+    )
+}
+
+fn not_a_function(ident: &Spanned<Identifier>) -> RoxError {
+    RoxError::with_file_placeholder(&format!(
+        "`{}` is not a function",
+        ident.value
+    ))
+    .with_label("not a function", ident.span.clone())
+}
+
+/// Translates an expression that should have the `expected` type, such as
+/// the initializer of a `let` with a type annotation. The expected type is
+/// what types empty arrays, unsuffixed number literals and calls to generic
+/// functions without explicit type arguments.
+fn translate_expression_with_expected_type(
+    type_env: &mut TypeEnv,
+    variable_env: &mut VariableEnv,
+    expression: Expression,
+    expected: &Type,
+) -> Result<TaggedExpression> {
+    let expected_constructor =
+        match expand(expected.without_empty_quantifier().clone()) {
+            Type::Apply(constructor, _) => Some(constructor),
+            _ => None,
+        };
+    match (expression, expected_constructor) {
+        (Expression::Array(elements), Some(TypeConstructor::Array(inner))) => {
+            let tagged_elements = elements
+                .into_iter()
+                .map(|element| {
+                    let tagged_element =
+                        translate_expression_with_expected_type(
+                            type_env,
+                            variable_env,
+                            *element,
+                            &inner,
+                        )?;
+                    unify(
+                        inner.as_ref().clone(),
+                        tagged_element.clone().into(),
+                    )?;
+                    Ok(tagged_element)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(TaggedExpression::Array(
+                tagged_elements,
+                Box::new(expected.clone()),
+            ))
+        }
+        (Expression::Tuple(elements), Some(TypeConstructor::Tuple(types)))
+            if elements.len() == types.len() =>
+        {
+            let tagged_elements = elements
+                .into_iter()
+                .zip(types.iter())
+                .map(|(element, element_type)| {
+                    translate_expression_with_expected_type(
+                        type_env,
+                        variable_env,
+                        *element,
+                        element_type,
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let element_types =
+                tagged_elements.iter().cloned().map(Type::from).collect();
+            Ok(TaggedExpression::Tuple(
+                tagged_elements,
+                Box::new(Type::Apply(
+                    TypeConstructor::Tuple(element_types),
+                    Vec::new(),
+                )),
+            ))
+        }
+        (Expression::Int(n, None), Some(constructor))
+            if constructor.is_integer() =>
+        {
+            int_literal(n, constructor, None)
+        }
+        (Expression::Unary(unary, operand), Some(constructor))
+            if matches!(unary.value, Unary::Negate)
+                && constructor.is_signed() =>
+        {
+            match *operand {
+                Expression::Int(n, None) => {
+                    int_literal(n, constructor, Some(&unary.span))
+                }
+                operand => translate_expression(
+                    type_env,
+                    variable_env,
+                    Expression::Unary(unary, Box::new(operand)),
+                ),
+            }
+        }
+        (Expression::Float(n, None), Some(constructor))
+            if constructor.is_float() =>
+        {
+            Ok(TaggedExpression::Float(
+                n,
+                Box::new(Type::Apply(constructor, Vec::new())),
+            ))
+        }
+        (Expression::FunctionCall(ident, generic_type_idents, args), _)
+            if generic_type_idents.is_empty() =>
+        {
+            let instantiated_generics =
+                infer_generic_arguments(variable_env, &ident.value, expected);
+            translate_function_call(
+                type_env,
+                variable_env,
+                ident,
+                instantiated_generics,
+                args,
+            )
+        }
+        (expression, _) => {
+            translate_expression(type_env, variable_env, expression)
+        }
+    }
+}
+
+/// Infers the type arguments of a call to a generic function from
+/// the type that the call should return
+fn infer_generic_arguments(
+    variable_env: &VariableEnv,
+    function_name: &str,
+    expected: &Type,
+) -> Vec<Type> {
+    match variable_env
+        .get(function_name)
+        .map(|binding| expand(binding.type_.clone()))
+    {
+        Some(Type::PolymorphicType(generics, function_type)) => {
+            let mut bindings = TypeEnv::new();
+            if let Type::Apply(_, types) = function_type.as_ref() {
+                if let Some(return_type) = types.last() {
+                    bind_type_variables(return_type, expected, &mut bindings);
+                }
+            }
+            generics
+                .iter()
+                .map(|generic| {
+                    bindings
+                        .get(generic)
+                        .cloned()
+                        .unwrap_or_else(|| Type::Variable(generic.clone()))
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Matches the type variables in `generic` with the parts of `actual`
+/// in the same positions
+fn bind_type_variables(generic: &Type, actual: &Type, bindings: &mut TypeEnv) {
+    match (generic, actual.without_empty_quantifier()) {
+        (Type::Variable(name), actual) => {
+            bindings
+                .entry(name.clone())
+                .or_insert_with(|| actual.clone());
+        }
+        (
+            Type::Apply(generic_constructor, generic_arguments),
+            Type::Apply(constructor, arguments),
+        ) => {
+            match (generic_constructor, constructor) {
+                (
+                    TypeConstructor::Array(generic_inner),
+                    TypeConstructor::Array(inner),
+                ) => bind_type_variables(generic_inner, inner, bindings),
+                (
+                    TypeConstructor::Tuple(generic_elements),
+                    TypeConstructor::Tuple(elements),
+                ) => generic_elements.iter().zip(elements).for_each(
+                    |(generic, actual)| {
+                        bind_type_variables(generic, actual, bindings)
+                    },
+                ),
+                _ => {}
+            }
+            generic_arguments.iter().zip(arguments).for_each(
+                |(generic, actual)| {
+                    bind_type_variables(generic, actual, bindings)
+                },
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Adds the variables bound by a `let` pattern to the environment
+fn bind_pattern(
+    variable_env: &mut VariableEnv,
+    pattern: &Pattern,
+    type_: Type,
+) -> Result<()> {
+    match pattern {
+        Pattern::Identifier(mutability, name) => {
+            variable_env.insert(
+                name.value.clone(),
+                Binding::variable(type_, *mutability, name.span.clone()),
+            );
+            Ok(())
+        }
+        Pattern::Tuple(patterns) => {
+            let elements = expand(type_.without_empty_quantifier().clone())
+                .get_tuple_elements()
+                .map(<[Type]>::to_vec)
+                .ok_or_else(|| {
+                    RoxError::with_file_placeholder(
+                        "Only tuples can be destructured",
+                    )
+                })?;
+            if elements.len() != patterns.len() {
+                return Err(RoxError::with_file_placeholder(
+                    format!(
+                        "Cannot destructure a tuple with {} elements into {} variables",
+                        elements.len(),
+                        patterns.len()
+                    )
+                    .as_ref(),
+                ));
+            }
+            patterns.iter().zip(elements).try_for_each(
+                |(pattern, element_type)| {
+                    bind_pattern(variable_env, pattern, element_type)
+                },
+            )
+        }
+    }
+}
+
 fn translate_type_identifier(
     type_env: &mut TypeEnv,
     ty: TypeName,
 ) -> Result<Type> {
     match ty {
         TypeName::Type(identifier) => {
-            Ok(type_env.get(&identifier.value).unwrap().clone())
+            type_env.get(&identifier.value).cloned().ok_or_else(|| {
+                RoxError::with_file_placeholder(
+                    format!("Unknown type `{}`", identifier.value).as_ref(),
+                )
+                .with_label("not found in this scope", identifier.span)
+            })
         }
         TypeName::ArrayType(type_) => {
             let inner_type =
@@ -803,8 +1869,11 @@ fn translate_type_identifier(
                 vec![],
             ))
         }
-        TypeName::GenericType(_identifier, _generic_types) => {
-            todo!()
+        TypeName::GenericType(identifier, _generic_types) => {
+            Err(RoxError::with_file_placeholder(
+                "Generic type annotations are not yet supported",
+            )
+            .with_label("not yet supported", identifier.span))
             // match type_env.get(&identifier.value).unwrap() {
             //     TypeValue::Type(t) => Ok(t.clone()), // N.B. This probably shouldn't happen, since formal parameters shouldn't be passed to a concrete type?
             //     TypeValue::Constructor(c) => Ok(Type::Apply(
@@ -821,6 +1890,15 @@ fn translate_type_identifier(
             //     )),
             // }
         }
+        TypeName::Tuple(element_types) => Ok(Type::Apply(
+            TypeConstructor::Tuple(
+                element_types
+                    .into_iter()
+                    .map(|t| translate_type_identifier(type_env, *t))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            Vec::new(),
+        )),
         TypeName::Function(mut argument_types, return_type) => {
             argument_types.push(return_type);
             Ok(Type::Apply(
@@ -839,21 +1917,541 @@ fn translate_type_identifier(
     }
 }
 
+/// Type checks a program, stopping at the first error
+#[cfg(test)]
 pub(crate) fn analyse_program(
     statements: Vec<Box<Statement>>,
 ) -> Result<Vec<TaggedStatement>> {
     let (mut type_env, mut variable_env, _) = builtins::get_builtin_types();
-    statements
-        .iter()
+    hoist_globals(statements)
         .map(|s| {
-            translate_statement(
-                &mut type_env,
-                &mut variable_env,
-                s.as_ref().clone(),
-            )
+            translate_top_level_statement(&mut type_env, &mut variable_env, *s)
         })
         .collect()
 }
 
+/// Type checks a program, carrying on with the next top-level statement
+/// after an error so that every error is reported
+pub(crate) fn check_program(
+    statements: Vec<Box<Statement>>,
+) -> std::result::Result<Vec<TaggedStatement>, Vec<RoxError>> {
+    let (mut type_env, mut variable_env, _) = builtins::get_builtin_types();
+    let mut tagged_statements = Vec::new();
+    let mut errors = Vec::new();
+    let has_main = statements.iter().any(|statement| {
+        matches!(
+            statement.as_ref(),
+            Statement::FunctionDeclaration(_, name, ..) if name.value == "main"
+        )
+    });
+    for statement in hoist_globals(statements) {
+        match translate_top_level_statement(
+            &mut type_env,
+            &mut variable_env,
+            *statement,
+        ) {
+            Ok(tagged_statement) => tagged_statements.push(tagged_statement),
+            Err(error) => errors.push(error),
+        }
+    }
+    if !has_main {
+        errors.push(
+            RoxError::with_file_placeholder(
+                "The program has no `main` function",
+            )
+            .with_note("help: declare one as `fn main() do ... end`"),
+        );
+    }
+    if errors.is_empty() {
+        Ok(tagged_statements)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Constants and statics are visible to every function, wherever they're
+/// declared, so they're moved ahead of the rest of the program. They keep
+/// their order, since their initializers can use the globals before them.
+fn hoist_globals(
+    statements: Vec<Box<Statement>>,
+) -> impl Iterator<Item = Box<Statement>> {
+    let (globals, rest): (Vec<_>, Vec<_>) =
+        statements.into_iter().partition(|statement| {
+            matches!(
+                statement.as_ref(),
+                Statement::ConstDeclaration(..)
+                    | Statement::StaticDeclaration(..)
+            )
+        });
+    globals.into_iter().chain(rest)
+}
+
+fn translate_top_level_statement(
+    type_env: &mut TypeEnv,
+    variable_env: &mut VariableEnv,
+    statement: Statement,
+) -> Result<TaggedStatement> {
+    let main_span = match &statement {
+        Statement::FunctionDeclaration(_, name, ..) if name.value == "main" => {
+            Some(name.span.clone())
+        }
+        _ => None,
+    };
+    let tagged_statement =
+        translate_statement(type_env, variable_env, statement, None)?;
+    if let Some(span) = main_span {
+        check_main_signature(&tagged_statement, span)?;
+    }
+    Ok(tagged_statement)
+}
+
+/// Checks that the program's `main`, whose name is at `span`, has one of
+/// the signatures that the generated C `main(argc, argv)` knows how to call
+fn check_main_signature(statement: &TaggedStatement, span: Span) -> Result<()> {
+    let declaration = match statement {
+        TaggedStatement::FunctionDeclaration(declaration, _) => declaration,
+        _ => return Ok(()),
+    };
+    let arguments = Type::Apply(
+        TypeConstructor::Array(Box::new(Type::Apply(
+            TypeConstructor::String,
+            Vec::new(),
+        ))),
+        Vec::new(),
+    );
+    let valid_parameters = match declaration.params.as_slice() {
+        [] => true,
+        [(_, type_)] => *type_ == arguments,
+        _ => false,
+    };
+    let valid_return_type = matches!(
+        declaration.return_type,
+        Type::Apply(TypeConstructor::Int, _)
+            | Type::Apply(TypeConstructor::Void, _)
+    );
+    if valid_parameters && valid_return_type {
+        Ok(())
+    } else {
+        Err(RoxError::with_file_placeholder(
+            "`main` has an invalid signature",
+        )
+        .with_label("`main` is declared here", span)
+        .with_note("`main` must be declared as `fn main()`, `fn main() -> Int`, `fn main(args: [String])` or `fn main(args: [String]) -> Int`"))
+    }
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::roxc::parse_string;
+    use std::path::PathBuf;
+
+    fn analyse_main_body(body: &str) -> Result<Vec<TaggedStatement>> {
+        let source = format!("fn main() do\n{}\nend", body);
+        analyse_program(parse_string(&source, &PathBuf::new())?)
+    }
+
+    #[test]
+    fn it_types_empty_arrays_from_annotations() {
+        let statements = analyse_main_body("let xs: [Int] = [];").unwrap();
+
+        match &statements[0] {
+            TaggedStatement::FunctionDeclaration(_, body) => assert!(matches!(
+                &body[0],
+                TaggedStatement::Expression(TaggedExpression::Variable(_, _, type_))
+                    if matches!(
+                        type_.as_ref(),
+                        Type::Apply(TypeConstructor::Array(inner), _)
+                            if **inner == Type::Apply(TypeConstructor::Int, Vec::new())
+                    )
+            )),
+            statement => panic!("Expected function, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn it_infers_generic_arguments_from_annotations() {
+        let source = r#"
+        fn empty<T>() -> [T] do
+            let xs: [T] = [];
+            return xs;
+        end
+
+        fn main() do
+            let strings: [String] = empty();
+        end
+        "#;
+
+        assert!(analyse_program(
+            parse_string(source, &PathBuf::new()).unwrap()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn it_reports_mismatched_annotations_at_the_annotation() {
+        let body = "let x: String = 5;";
+        let error = analyse_main_body(body).unwrap_err();
+        let annotation_start = "fn main() do\nlet x: ".len();
+
+        assert_eq!(error.labels.len(), 1);
+        assert_eq!(
+            error.labels[0].range,
+            annotation_start..annotation_start + "String".len()
+        );
+    }
+
+    #[test]
+    fn it_rejects_assignment_to_immutable_variables() {
+        let error = analyse_main_body("let xs = [1];\nxs[0] = 2;").unwrap_err();
+        let declaration_start = "fn main() do\nlet ".len();
+
+        assert_eq!(error.labels.len(), 2);
+        assert_eq!(
+            error.labels[1].range,
+            declaration_start..declaration_start + "xs".len()
+        );
+        assert!(error.notes[0].contains("mut xs"));
+    }
+
+    #[test]
+    fn it_suggests_static_mut_for_immutable_statics() {
+        let source = "static count: Int = 1;\nfn main() do\ncount = 2;\nend";
+
+        let error =
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap_err();
+        assert!(error.notes[0].contains("`static mut count`"));
+    }
+
+    #[test]
+    fn it_rejects_assignment_to_immutable_parameters() {
+        let source = "fn f(count: Int) do\ncount = 1;\nend";
+
+        assert!(analyse_program(
+            parse_string(source, &PathBuf::new()).unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn it_allows_assignment_to_mutable_bindings() {
+        assert!(analyse_main_body("let mut x = 1;\nx = 2;").is_ok());
+    }
+
+    #[test]
+    fn it_inlines_constants() {
+        let source = "const LIMIT: Int64 = 10;\nfn main() do\nLIMIT;\nend";
+        let statements =
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap();
+
+        match &statements[1] {
+            TaggedStatement::FunctionDeclaration(_, body) => assert!(matches!(
+                &body[0],
+                TaggedStatement::Expression(TaggedExpression::Int(value, type_))
+                    if value.value == 10
+                        && **type_ == Type::Apply(TypeConstructor::Int64, Vec::new())
+            )),
+            statement => panic!("Expected function, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn it_rejects_non_constant_initializers_and_assignment_to_constants() {
+        let analyse = |source: &str| {
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+        };
+
+        assert!(analyse(
+            "fn one() -> Int do return 1; end\nconst ONE: Int = one();"
+        )
+        .is_err());
+        assert!(analyse("const ONE: Int = 1;\nfn main() do\nONE = 2;\nend")
+            .is_err());
+        assert!(analyse(
+            "static mut count: Int = 1;\nfn main() do\ncount = 2;\nend"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn it_checks_integer_literals_against_their_type() {
+        assert!(analyse_main_body("let x: Int64 = 3000000000;").is_ok());
+        assert!(analyse_main_body("let x: UInt32 = 4000000000;").is_ok());
+        assert!(analyse_main_body("let x = 3000000000i64;").is_ok());
+        assert!(analyse_main_body("let x = 3000000000;").is_err());
+        assert!(analyse_main_body("let x: UInt8 = 256;").is_err());
+        assert!(analyse_main_body("let x = 256u8;").is_err());
+    }
+
+    #[test]
+    fn it_accepts_the_minimum_values_of_signed_types() {
+        assert!(analyse_main_body("let x = -2147483648;").is_ok());
+        assert!(analyse_main_body("let x = -128i8;").is_ok());
+        assert!(analyse_main_body("let x: Int8 = -128;").is_ok());
+        assert!(
+            analyse_main_body("let x: Int64 = -9223372036854775808;").is_ok()
+        );
+        assert!(analyse_main_body("let x = -2147483649;").is_err());
+        assert!(analyse_main_body("let x = -129i8;").is_err());
+    }
+
+    #[test]
+    fn it_compares_bools_for_equality() {
+        assert!(analyse_main_body("let x = true == false;").is_ok());
+        assert!(analyse_main_body("let x = true != false;").is_ok());
+        assert!(analyse_main_body("let x = true > false;").is_err());
+        assert!(analyse_main_body("let x = true + false;").is_err());
+    }
+
+    #[test]
+    fn it_makes_globals_visible_to_functions_declared_before_them() {
+        let source = r#"
+        fn main() -> Int do
+            count = count + 1;
+            return LIMIT + count;
+        end
+
+        const LIMIT: Int = 10;
+        static mut count: Int = LIMIT;
+        "#;
+
+        assert!(analyse_program(
+            parse_string(source, &PathBuf::new()).unwrap()
+        )
+        .is_ok());
+        assert!(
+            check_program(parse_string(source, &PathBuf::new()).unwrap())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn it_reports_lets_used_as_values_as_unsupported() {
+        let error = analyse_main_body("let x = let y = 1;").unwrap_err();
+
+        assert_eq!(
+            error.message.as_deref(),
+            Some("Using a `let` as a value is not yet supported")
+        );
+        assert_eq!(error.labels[0].range, 25..26);
+    }
+
+    #[test]
+    fn it_types_unsuffixed_literals_from_the_other_operand() {
+        assert!(analyse_main_body(
+            "let big: Int64 = 1;\nlet byte: UInt8 = 2;\nbig + 1;\n1 - big;\nbyte << 1;"
+        )
+        .is_ok());
+        assert!(analyse_main_body("let byte: UInt8 = 2;\nbyte + 256;").is_err());
+    }
+
+    #[test]
+    fn it_types_unsuffixed_arguments_from_their_parameters() {
+        let source = "fn wide(value: Int64) -> Int64 do\nreturn value;\nend\nfn main() do\nwide(1);\nend";
+
+        assert!(analyse_program(
+            parse_string(source, &PathBuf::new()).unwrap()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn it_checks_returned_values_against_the_return_type() {
+        let check = |source: &str| {
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+        };
+
+        assert!(check("fn wide() -> Int64 do\nreturn 1;\nend").is_ok());
+        let error =
+            check("fn wide() -> Int64 do\nreturn \"one\";\nend").unwrap_err();
+        assert_eq!(error.message.as_deref(), Some("Mismatched types"));
+        assert_eq!(error.labels[0].range, 29..34);
+        let error = check("fn wide() -> Int64 do\nreturn;\nend").unwrap_err();
+        assert_eq!(error.labels[0].range, 22..28);
+    }
+
+    #[test]
+    fn it_reports_calls_to_undefined_functions() {
+        let source = "fn main() do\nlater();\nend\nfn later() do\nend";
+
+        let error =
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap_err();
+        assert_eq!(
+            error.message.as_deref(),
+            Some("Cannot find function `later`")
+        );
+        assert_eq!(error.labels[0].range, 13..18);
+    }
+
+    #[test]
+    fn it_interpolates_chars() {
+        let source =
+            "fn main() do\nlet letter = 'a';\nlet s = \"{letter}\";\nend";
+
+        let statements = parse_string(source, &PathBuf::new()).unwrap();
+        assert!(analyse_program(statements).is_ok());
+    }
+
+    #[test]
+    fn it_labels_values_that_cannot_be_interpolated() {
+        let source =
+            "fn main() do\nlet pair = (1, 2);\nlet s = \"{pair}\";\nend";
+
+        let error =
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap_err();
+        assert_eq!(error.labels[0].message, "cannot be interpolated");
+        assert_eq!(error.labels[0].range, 42..46);
+    }
+
+    #[test]
+    fn it_checks_the_number_of_arguments() {
+        let source = "fn add(a: Int, b: Int) -> Int do\nreturn a + b;\nend\nfn main() do\nadd(1);\nend";
+
+        let error =
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap_err();
+        assert_eq!(error.message.as_deref(), Some("`add` takes 2 arguments"));
+        assert_eq!(error.labels[0].message, "expected 2 arguments, found 1");
+        assert_eq!(error.labels[0].range, 64..67);
+    }
+
+    #[test]
+    fn it_requires_annotations_for_empty_arrays() {
+        assert!(analyse_main_body("let xs = [];").is_err());
+    }
+
+    #[test]
+    fn it_evaluates_constant_initializers() {
+        let source =
+            "const KIB: Int = 1 << 10;\nconst MIB: Int = KIB * KIB;\nfn main() do\nMIB;\nend";
+        let statements =
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap();
+
+        match &statements[2] {
+            TaggedStatement::FunctionDeclaration(_, body) => assert!(matches!(
+                &body[0],
+                TaggedStatement::Expression(TaggedExpression::Int(value, _))
+                    if value.value == 1 << 20
+            )),
+            statement => panic!("Expected function, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn it_reports_division_by_zero_and_overflow_in_constants() {
+        assert!(analyse_main_body("let x = 1 / (2 - 2);").is_err());
+        assert!(analyse_main_body("let x = 2147483647 + 1;").is_err());
+        assert!(analyse_main_body("let x = 255u8 + 1u8;").is_err());
+        assert!(analyse_main_body("let x = 2147483647i64 + 1i64;").is_ok());
+    }
+
+    #[test]
+    fn it_evaluates_array_lengths() {
+        assert!(analyse_main_body("let xs = [0; 2 * 4];").is_ok());
+        assert!(analyse_main_body("let xs = [0; 0 - 1];").is_err());
+        assert!(analyse_main_body("let n = 4;\nlet xs = [0; n];").is_err());
+    }
+
+    #[test]
+    fn it_reports_errors_from_every_function() {
+        let source = r#"
+        fn first() -> Int do
+            return unknown;
+        end
+
+        fn second() do
+            let x = 1;
+            x = 2;
+        end
+
+        fn main() do
+            first();
+            second();
+        end
+        "#;
+        let errors =
+            check_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn it_reports_programs_without_main() {
+        let source = "fn helper() do\nend";
+        let errors =
+            check_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap_err();
+
+        assert_eq!(
+            errors[0].message.as_deref(),
+            Some("The program has no `main` function")
+        );
+    }
+
+    #[test]
+    fn it_reports_calls_of_values_that_are_not_functions() {
+        let error = analyse_main_body("let x = 1;\nx();").unwrap_err();
+
+        assert_eq!(error.message.as_deref(), Some("`x` is not a function"));
+    }
+
+    #[test]
+    fn it_reports_struct_instantiations_with_missing_fields() {
+        let source = r#"
+        struct Person {
+            name: String,
+            age: Int,
+        }
+
+        fn main() do
+            let person = Person { name: "Ada" };
+        end
+        "#;
+        let error =
+            analyse_program(parse_string(source, &PathBuf::new()).unwrap())
+                .unwrap_err();
+
+        assert_eq!(
+            error.message.as_deref(),
+            Some("Missing field `age` in `Person`")
+        );
+    }
+
+    #[test]
+    fn it_accepts_the_signatures_of_main() {
+        let signatures = [
+            "fn main() do\nend",
+            "fn main() -> Int do\nreturn 0;\nend",
+            "fn main(args: [String]) do\nend",
+            "fn main(args: [String]) -> Int do\nreturn 0;\nend",
+        ];
+        for source in signatures.iter() {
+            let statements = parse_string(source, &PathBuf::new()).unwrap();
+            assert!(analyse_program(statements).is_ok(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn it_rejects_other_signatures_of_main() {
+        let signatures = [
+            "fn main() -> String do\nreturn \"\";\nend",
+            "fn main(code: Int) do\nend",
+            "fn main(args: [String], code: Int) do\nend",
+        ];
+        for source in signatures.iter() {
+            let statements = parse_string(source, &PathBuf::new()).unwrap();
+            let error = analyse_program(statements).unwrap_err();
+            assert_eq!(
+                error.message.as_deref(),
+                Some("`main` has an invalid signature"),
+            );
+            assert_eq!(error.labels[0].range, 3..7);
+        }
+    }
+}
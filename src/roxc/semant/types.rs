@@ -4,14 +4,27 @@ use crate::roxc::Identifier;
 #[allow(dead_code)]
 pub enum TypeConstructor {
     Bool,
+    /// A Unicode scalar value, stored as a 32 bit integer
+    Char,
     Float,
+    Float32,
+    /// `Int` is a 32 bit signed integer, and is also available as `Int32`
     Int,
+    Int8,
+    Int16,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
     String,
     Void,
     Arrow,
     Array(Box<Type>),
     /// The Record type constructor takes a list of field name identifiers and their types
     Record(Vec<(Identifier, Type)>),
+    /// The types of the elements of a tuple, such as `(Int, String)`
+    Tuple(Vec<Type>),
     /// Takes the list of formal type parameters and the return type
     FunctionType(Vec<Identifier>, Box<Type>),
     /// `Unique` type constructors exist to differentiate two named types with the same fields.
@@ -25,10 +38,58 @@ pub enum TypeConstructor {
 }
 
 impl TypeConstructor {
-    pub fn get_record_fields(&self) -> Vec<(Identifier, Type)> {
+    pub fn is_integer(&self) -> bool {
+        use TypeConstructor::*;
+        matches!(
+            self,
+            Int | Int8 | Int16 | Int64 | UInt8 | UInt16 | UInt32 | UInt64
+        )
+    }
+
+    /// Whether integer operations on this type, such as division and
+    /// comparison, should treat the operands as signed
+    pub fn is_signed(&self) -> bool {
+        use TypeConstructor::*;
+        matches!(self, Int | Int8 | Int16 | Int64)
+    }
+
+    /// The largest value of an integer type, or `None` for other types
+    pub fn max_int(&self) -> Option<u64> {
+        use TypeConstructor::*;
+        match self {
+            Int8 => Some(i8::MAX as u64),
+            Int16 => Some(i16::MAX as u64),
+            Int => Some(i32::MAX as u64),
+            Int64 => Some(i64::MAX as u64),
+            UInt8 => Some(u8::MAX as u64),
+            UInt16 => Some(u16::MAX as u64),
+            UInt32 => Some(u32::MAX as u64),
+            UInt64 => Some(u64::MAX),
+            _ => None,
+        }
+    }
+
+    /// The number of bits in an integer type, or `None` for other types
+    pub fn bit_width(&self) -> Option<u32> {
+        use TypeConstructor::*;
+        match self {
+            Int8 | UInt8 => Some(8),
+            Int16 | UInt16 => Some(16),
+            Int | UInt32 => Some(32),
+            Int64 | UInt64 => Some(64),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, TypeConstructor::Float | TypeConstructor::Float32)
+    }
+
+    /// The fields of a struct type, or `None` for other types
+    pub fn get_record_fields(&self) -> Option<Vec<(Identifier, Type)>> {
         match self {
-            TypeConstructor::Record(fields) => fields.clone(),
-            _ => panic!("Tried to get records for non-record type"), // TODO: improve error handling
+            TypeConstructor::Record(fields) => Some(fields.clone()),
+            _ => None,
         }
     }
 }
@@ -43,10 +104,33 @@ pub enum Type {
 }
 
 impl Type {
-    pub fn get_record_fields(&self) -> Vec<(Identifier, Type)> {
+    /// Non-generic type declarations are still wrapped in a
+    /// `PolymorphicType` with no formal parameters. This unwraps them
+    /// so they can be compared with their instances.
+    pub fn without_empty_quantifier(&self) -> &Type {
         match self {
+            Type::PolymorphicType(formal_parameters, type_)
+                if formal_parameters.is_empty() =>
+            {
+                type_.without_empty_quantifier()
+            }
+            _ => self,
+        }
+    }
+
+    /// The element types of a tuple type, or `None` for other types
+    pub fn get_tuple_elements(&self) -> Option<&[Type]> {
+        match self.without_empty_quantifier() {
+            Type::Apply(TypeConstructor::Tuple(elements), _) => Some(elements),
+            _ => None,
+        }
+    }
+
+    /// The fields of a struct type, or `None` for other types
+    pub fn get_record_fields(&self) -> Option<Vec<(Identifier, Type)>> {
+        match self.without_empty_quantifier() {
             Type::Apply(constructor, _) => constructor.get_record_fields(),
-            _ => panic!("Tried to get record fields for non-record type"), // TODO: improve error handling
+            _ => None,
         }
     }
 }
@@ -0,0 +1,156 @@
+use crate::roxc::{OptimizationLevel, Result, RoxError};
+use inkwell::module::Module;
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target,
+    TargetMachine, TargetTriple,
+};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The machine that a Rox program is compiled for
+#[derive(Clone, Debug, Default)]
+pub struct TargetOptions {
+    /// The LLVM target triple, such as `x86_64-unknown-linux-gnu`.
+    /// Programs are compiled for the host by default.
+    pub triple: Option<String>,
+    /// The CPU to generate code for, or `native` for the host's CPU.
+    /// Code is generated for a generic CPU by default.
+    pub cpu: Option<String>,
+    /// A comma separated list of CPU features to enable or disable,
+    /// such as `+avx2,-sse4.1`
+    pub features: Option<String>,
+    /// How code and data are addressed in the object file
+    pub relocation_model: RelocationModel,
+}
+
+/// The relocation models accepted by `--relocation-model`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RelocationModel {
+    /// Position independent code, which is the default because most
+    /// systems' `cc` links position independent executables
+    #[default]
+    Pic,
+    /// Non-relocatable code
+    Static,
+    /// Code that is relocatable, but refers to external data with absolute addresses
+    DynamicNoPic,
+    /// The target's default
+    Default,
+}
+
+impl FromStr for RelocationModel {
+    type Err = String;
+
+    fn from_str(model: &str) -> std::result::Result<Self, Self::Err> {
+        match model {
+            "pic" => Ok(RelocationModel::Pic),
+            "static" => Ok(RelocationModel::Static),
+            "dynamic-no-pic" => Ok(RelocationModel::DynamicNoPic),
+            "default" => Ok(RelocationModel::Default),
+            _ => Err(format!(
+                "Unknown relocation model `{}`, expected one of pic, static, dynamic-no-pic or default",
+                model
+            )),
+        }
+    }
+}
+
+impl From<RelocationModel> for RelocMode {
+    fn from(model: RelocationModel) -> Self {
+        match model {
+            RelocationModel::Pic => RelocMode::PIC,
+            RelocationModel::Static => RelocMode::Static,
+            RelocationModel::DynamicNoPic => RelocMode::DynamicNoPic,
+            RelocationModel::Default => RelocMode::Default,
+        }
+    }
+}
+
+impl TargetOptions {
+    pub(crate) fn triple(&self) -> TargetTriple {
+        match &self.triple {
+            Some(triple) => TargetTriple::create(triple),
+            None => TargetMachine::get_default_triple(),
+        }
+    }
+
+    pub(crate) fn create_target_machine(
+        &self,
+        optimization_level: OptimizationLevel,
+    ) -> Result<TargetMachine> {
+        Target::initialize_all(&InitializationConfig::default());
+        let triple = self.triple();
+        let triple_name = triple.as_str().to_string_lossy();
+        let target = Target::from_triple(&triple).map_err(|error| {
+            RoxError::with_file_placeholder(&format!(
+                "Unsupported target `{}`",
+                triple_name
+            ))
+            .with_note(error.to_str().unwrap_or_default())
+        })?;
+        let (cpu, features) = match self.cpu.as_deref() {
+            Some("native") => (
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string(),
+            ),
+            Some(cpu) => (cpu.to_string(), String::new()),
+            None => ("generic".to_string(), String::new()),
+        };
+        // Explicit features are applied on top of the CPU's features
+        let features = match &self.features {
+            Some(explicit) if features.is_empty() => explicit.clone(),
+            Some(explicit) => format!("{},{}", features, explicit),
+            None => features,
+        };
+        target
+            .create_target_machine(
+                &triple,
+                &cpu,
+                &features,
+                optimization_level.llvm_level(),
+                self.relocation_model.into(),
+                CodeModel::Default,
+            )
+            .ok_or_else(|| {
+                RoxError::with_file_placeholder(&format!(
+                    "Could not create a target machine for `{}` with CPU `{}`",
+                    triple_name, cpu
+                ))
+            })
+    }
+}
+
+/// Writes the module to an object file for the target
+pub(crate) fn write_object_file(
+    target_machine: &TargetMachine,
+    module: &Module,
+    path: &Path,
+) -> Result<()> {
+    write_file(target_machine, module, FileType::Object, path)
+}
+
+/// Writes the module's assembly for the target
+pub(crate) fn write_assembly_file(
+    target_machine: &TargetMachine,
+    module: &Module,
+    path: &Path,
+) -> Result<()> {
+    write_file(target_machine, module, FileType::Assembly, path)
+}
+
+fn write_file(
+    target_machine: &TargetMachine,
+    module: &Module,
+    file_type: FileType,
+    path: &Path,
+) -> Result<()> {
+    target_machine
+        .write_to_file(module, file_type, path)
+        .map_err(|error| {
+            RoxError::with_file_placeholder(&format!(
+                "Failed to write `{}`",
+                path.display()
+            ))
+            .with_note(error.to_str().unwrap_or_default())
+        })
+}